@@ -0,0 +1,75 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Files are never rewritten in place. The new contents go to a temporary
+// file next to the target, which is synced and renamed over it, and then the
+// directory is synced so the rename itself survives a crash. Whatever
+// happens midway, the target holds either all of the old contents or all of
+// the new.
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Replaces the contents of `path` with whatever `write` writes to the file
+/// it is handed. `path` is left untouched if `write` or anything after it
+/// fails.
+pub fn replace_file_with<E, F>(path: &Path, write: F) -> Result<(), E>
+where
+    E: From<io::Error>,
+    F: FnOnce(&mut File) -> Result<(), E>,
+{
+    let tmp_path = tmp_path(path);
+    let result = write_and_rename(path, &tmp_path, write);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Replaces the contents of `path` with `data`, see `replace_file_with`.
+pub fn replace_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    replace_file_with(path, |file| file.write_all(data))
+}
+
+fn write_and_rename<E, F>(path: &Path, tmp_path: &Path, write: F) -> Result<(), E>
+where
+    E: From<io::Error>,
+    F: FnOnce(&mut File) -> Result<(), E>,
+{
+    let mut tmp = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(tmp_path)?;
+    // Keep the permissions of the file being replaced
+    if let Ok(metadata) = fs::metadata(path) {
+        tmp.set_permissions(metadata.permissions())?;
+    }
+    write(&mut tmp)?;
+    tmp.sync_all()?;
+    drop(tmp);
+    fs::rename(tmp_path, path)?;
+    sync_dir(path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened as files on Windows, so there is nothing to
+// sync there
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::atomic::replace_file;
+use crate::encryption::encryption::replace_header;
+use crate::encryption::header::VaultHeader;
+use crate::lock::StoreLock;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use orion::aead::SecretKey;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Before a store is replaced, the file about to be replaced is kept next to
+// it as `<store>.<timestamp>.bak`, so a bad save can be undone. Backups are
+// the store as it was on disk, still encrypted. Since they are encrypted
+// under the same data key as the store, their headers are replaced whenever
+// the store's key slots change, so a removed slot or replaced password opens
+// none of them. Only the newest few generations are kept.
+
+/// How many backups are kept unless told otherwise.
+pub const DEFAULT_GENERATIONS: usize = 10;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.9f";
+const SUFFIX: &str = ".bak";
+
+/// A backup of a store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created: DateTime<Utc>,
+}
+
+fn file_prefix(store_path: &Path) -> Option<String> {
+    store_path
+        .file_name()
+        .map(|name| format!("{}.", name.to_string_lossy()))
+}
+
+fn parent_dir(store_path: &Path) -> &Path {
+    match store_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Keeps the current contents of `store_path` as a new backup, then removes
+/// all but the newest `generations` backups. Does nothing if there is no
+/// store yet or `generations` is 0.
+pub fn back_up(store_path: &Path, generations: usize) -> io::Result<Option<Backup>> {
+    if generations == 0 || !store_path.exists() {
+        return Ok(None);
+    }
+    let created = Utc::now();
+    let mut name = store_path.as_os_str().to_owned();
+    name.push(format!(".{}{}", created.format(TIMESTAMP_FORMAT), SUFFIX));
+    let path = PathBuf::from(name);
+
+    fs::copy(store_path, &path)?;
+    File::open(&path)?.sync_all()?;
+    prune(store_path, generations)?;
+    Ok(Some(Backup { path, created }))
+}
+
+/// Lists the backups of `store_path`, newest first.
+pub fn list(store_path: &Path) -> io::Result<Vec<Backup>> {
+    let prefix = match file_prefix(store_path) {
+        Some(prefix) => prefix,
+        None => return Ok(Vec::new()),
+    };
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(parent_dir(store_path))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let timestamp = match name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(SUFFIX))
+        {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+        // Skips other files that happen to match, like the legacy store backup
+        if let Ok(created) = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT) {
+            backups.push(Backup {
+                path: entry.path(),
+                created: Utc.from_utc_datetime(&created),
+            });
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+    Ok(backups)
+}
+
+/// Removes all but the newest `generations` backups of `store_path`.
+pub fn prune(store_path: &Path, generations: usize) -> io::Result<()> {
+    for backup in list(store_path)?.iter().skip(generations) {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(())
+}
+
+/// Gives every backup of `store_path` the key slots of `header`, which has
+/// to wrap `key`, the data key they are encrypted with. Returns the backups
+/// that couldn't be given them, like ones of another vault or format; they
+/// are left as they are and still open with their old slots. The store
+/// should be locked while this runs.
+pub fn reseal(store_path: &Path, key: &SecretKey, header: &VaultHeader) -> io::Result<Vec<Backup>> {
+    let mut failed = Vec::new();
+    for backup in list(store_path)? {
+        match replace_header(&fs::read(&backup.path)?, key, header) {
+            Ok(resealed) => replace_file(&backup.path, &resealed)?,
+            Err(_) => failed.push(backup),
+        }
+    }
+    Ok(failed)
+}
+
+/// Puts `backup` back in place of `store_path`. The store being replaced is
+/// backed up first, so a restore can be undone like any other save.
+pub fn restore(store_path: &Path, backup: &Backup, generations: usize) -> io::Result<()> {
+    // Read before backing up, which may prune the backup being restored
+    let contents = fs::read(&backup.path)?;
+    let _lock = StoreLock::acquire(store_path)?;
+    back_up(store_path, generations)?;
+    replace_file(store_path, &contents)
+}
@@ -0,0 +1,180 @@
+// Command line handling. Without a subcommand arustylock starts the TUI.
+
+use arustylock::backup::DEFAULT_GENERATIONS;
+use arustylock::encryption::header::Cipher;
+use arustylock::stores;
+use arustylock::vault::DEFAULT_HISTORY_DEPTH;
+
+pub const USAGE: &str = "Usage: arustylock [OPTIONS] [COMMAND]
+
+Commands:
+    change-password           Replace the master password
+    recover                   Set a new master password using the recovery key or
+                              enough of its shares
+    key-slots                 List the key slots that can open the password store
+    add-key-slot password     Add another password that opens the store
+    add-key-slot recovery     Add a recovery passphrase that opens the store
+    add-key-slot keyfile PATH Add a keyfile that opens the store on its own
+    add-key-slot recovery-key Generate a new recovery key, replacing the old one
+    split-recovery-key K N    Replace the recovery key with N shares, any K of
+                              which can recover the store
+    remove-key-slot N         Remove key slot N
+    list [FOLDER]             List the entries with their folders, or only those
+                              in FOLDER
+    vaults                    List the password stores that can be opened with
+                              --vault
+    backups                   List the backups of the store, newest first
+    restore-backup N          Replace the store with backup N
+    help                      Print this message
+
+Options:
+    --vault NAME       Use the password store called NAME instead of the default
+                       one, creating it if there is none yet. Names can have
+                       letters, digits, - and _.
+    --keyfile PATH     Use the contents of PATH as a second unlock factor. A new
+                       store created with this option can only be opened with
+                       the same keyfile.
+    --hardened         Disable core dumps and debugger attaching, and lock all
+                       of the process's memory so the decrypted store is never
+                       swapped out. Needs a memory lock limit of at least
+                       128 MiB. Linux only.
+    --cipher NAME      Encrypt a new store with NAME, either xchacha20-poly1305
+                       (the default) or aes-256-gcm. Existing stores keep the
+                       cipher they were created with.
+    --backups N        Keep the N newest backups of the store, 0 for none.
+                       Defaults to 10.
+    --history N        Keep the N most recent previous logins of each entry, 0
+                       for none. Defaults to 5.
+
+Without a command the interactive interface is started.";
+
+pub enum NewSlot {
+    Password,
+    Recovery,
+    Keyfile(String),
+}
+
+pub enum Subcommand {
+    Tui,
+    ChangePassword,
+    Recover,
+    KeySlots,
+    AddKeySlot(NewSlot),
+    NewRecoveryKey,
+    SplitRecoveryKey { threshold: u8, count: u8 },
+    RemoveKeySlot(usize),
+    List(Option<String>),
+    Vaults,
+    Backups,
+    RestoreBackup(usize),
+    Help,
+}
+
+pub struct Args {
+    pub subcommand: Subcommand,
+    pub vault: String,
+    pub keyfile: Option<String>,
+    pub hardened: bool,
+    pub cipher: Cipher,
+    pub backups: usize,
+    pub history: usize,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut vault = String::from(stores::DEFAULT);
+    let mut keyfile = None;
+    let mut hardened = false;
+    let mut cipher = Cipher::XChaCha20Poly1305;
+    let mut backups = DEFAULT_GENERATIONS;
+    let mut history = DEFAULT_HISTORY_DEPTH;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keyfile" => match args.next() {
+                Some(path) => keyfile = Some(path),
+                None => return Err(String::from("--keyfile needs a path")),
+            },
+            "--vault" => match args.next() {
+                Some(name) if stores::valid_name(&name) => vault = name,
+                Some(_) => {
+                    return Err(String::from(
+                        "vault names can only have letters, digits, - and _",
+                    ))
+                }
+                None => return Err(String::from("--vault needs a name")),
+            },
+            "--hardened" => hardened = true,
+            "--cipher" => match args.next().as_deref().map(Cipher::from_name) {
+                Some(Some(chosen)) => cipher = chosen,
+                Some(None) => {
+                    return Err(String::from(
+                        "--cipher takes xchacha20-poly1305 or aes-256-gcm",
+                    ))
+                }
+                None => return Err(String::from("--cipher needs a cipher name")),
+            },
+            "--backups" => match args.next().map(|count| count.parse()) {
+                Some(Ok(count)) => backups = count,
+                Some(Err(_)) => return Err(String::from("--backups takes a number")),
+                None => return Err(String::from("--backups needs a number")),
+            },
+            "--history" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => history = depth,
+                Some(Err(_)) => return Err(String::from("--history takes a number")),
+                None => return Err(String::from("--history needs a number")),
+            },
+            "--help" | "-h" => positional.insert(0, String::from("help")),
+            _ => positional.push(arg),
+        }
+    }
+
+    let words: Vec<&str> = positional.iter().map(String::as_str).collect();
+    let subcommand = match words.as_slice() {
+        [] => Subcommand::Tui,
+        ["help", ..] => Subcommand::Help,
+        ["change-password"] => Subcommand::ChangePassword,
+        ["recover"] => Subcommand::Recover,
+        ["key-slots"] => Subcommand::KeySlots,
+        ["add-key-slot", "password"] => Subcommand::AddKeySlot(NewSlot::Password),
+        ["add-key-slot", "recovery"] => Subcommand::AddKeySlot(NewSlot::Recovery),
+        ["add-key-slot", "recovery-key"] => Subcommand::NewRecoveryKey,
+        ["add-key-slot", "keyfile", path] => {
+            Subcommand::AddKeySlot(NewSlot::Keyfile(path.to_string()))
+        }
+        ["add-key-slot", ..] => {
+            return Err(String::from(
+                "add-key-slot takes 'password', 'recovery', 'recovery-key' or 'keyfile PATH'",
+            ))
+        }
+        ["split-recovery-key", threshold, count] => match (threshold.parse(), count.parse()) {
+            (Ok(threshold), Ok(count)) => Subcommand::SplitRecoveryKey { threshold, count },
+            _ => {
+                return Err(String::from(
+                    "split-recovery-key takes two numbers up to 255",
+                ))
+            }
+        },
+        ["remove-key-slot", index] => match index.parse() {
+            Ok(index) => Subcommand::RemoveKeySlot(index),
+            Err(_) => return Err(format!("'{}' is not a key slot number", index)),
+        },
+        ["list"] => Subcommand::List(None),
+        ["list", folder] => Subcommand::List(Some(folder.to_string())),
+        ["vaults"] => Subcommand::Vaults,
+        ["backups"] => Subcommand::Backups,
+        ["restore-backup", index] => match index.parse() {
+            Ok(index) => Subcommand::RestoreBackup(index),
+            Err(_) => return Err(format!("'{}' is not a backup number", index)),
+        },
+        _ => return Err(format!("unknown command '{}'", words.join(" "))),
+    };
+    Ok(Args {
+        subcommand,
+        vault,
+        keyfile,
+        hardened,
+        cipher,
+        backups,
+        history,
+    })
+}
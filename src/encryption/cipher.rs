@@ -0,0 +1,240 @@
+use aes_gcm::aead::{generic_array::GenericArray, AeadInPlace, KeyInit};
+use orion::aead::SecretKey;
+use orion::errors::UnknownCryptoError;
+use orion::hazardous::aead::xchacha20poly1305;
+use orion::hazardous::stream::{chacha20, xchacha20};
+use zeroize::Zeroizing;
+
+// The AEADs a vault can be encrypted with. The cipher's id is recorded in the
+// vault header and used both for the body and for wrapping the data key in
+// the key slots, so a vault is always opened with the cipher it was created
+// with.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    XChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+impl Cipher {
+    pub const ALL: [Cipher; 2] = [Cipher::XChaCha20Poly1305, Cipher::Aes256Gcm];
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Cipher::XChaCha20Poly1305 => 1,
+            Cipher::Aes256Gcm => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Cipher> {
+        match id {
+            1 => Some(Cipher::XChaCha20Poly1305),
+            2 => Some(Cipher::Aes256Gcm),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::XChaCha20Poly1305 => "xchacha20-poly1305",
+            Cipher::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Cipher> {
+        Cipher::ALL
+            .iter()
+            .copied()
+            .find(|cipher| cipher.name() == name)
+    }
+
+    pub fn backend(self) -> &'static dyn CipherBackend {
+        match self {
+            Cipher::XChaCha20Poly1305 => &XChaChaBackend,
+            Cipher::Aes256Gcm => &AesGcmBackend,
+        }
+    }
+
+    /// Seals `plaintext` under a fresh random nonce, returning
+    /// `nonce || ciphertext || tag`.
+    pub fn seal(
+        self,
+        key: &SecretKey,
+        plaintext: &[u8],
+        ad: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let backend = self.backend();
+        let nonce_len = backend.nonce_len();
+        let mut sealed = vec![0u8; nonce_len + plaintext.len() + backend.tag_len()];
+        orion::util::secure_rand_bytes(&mut sealed[..nonce_len])?;
+        let (nonce, body) = sealed.split_at_mut(nonce_len);
+        backend.seal(key, nonce, plaintext, ad, body)?;
+        Ok(sealed)
+    }
+
+    /// Opens what `seal` produced.
+    pub fn open(
+        self,
+        key: &SecretKey,
+        sealed: &[u8],
+        ad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let backend = self.backend();
+        let nonce_len = backend.nonce_len();
+        if sealed.len() <= nonce_len + backend.tag_len() {
+            return Err(UnknownCryptoError);
+        }
+        let mut plaintext = Zeroizing::new(vec![0u8; sealed.len() - nonce_len - backend.tag_len()]);
+        backend.open(
+            key,
+            &sealed[..nonce_len],
+            &sealed[nonce_len..],
+            ad,
+            &mut plaintext,
+        )?;
+        Ok(plaintext)
+    }
+}
+
+/// An AEAD a vault can be sealed with.
+pub trait CipherBackend {
+    fn nonce_len(&self) -> usize;
+
+    fn tag_len(&self) -> usize;
+
+    /// Encrypts `plaintext` into `out`, which takes the ciphertext followed by
+    /// the tag and must be exactly that long.
+    fn seal(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        plaintext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError>;
+
+    /// Checks the tag at the end of `ciphertext` and decrypts the rest into
+    /// `out`, which must be exactly as long as the plaintext.
+    fn open(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError>;
+}
+
+struct XChaChaBackend;
+
+impl CipherBackend for XChaChaBackend {
+    fn nonce_len(&self) -> usize {
+        xchacha20::XCHACHA_NONCESIZE
+    }
+
+    fn tag_len(&self) -> usize {
+        16
+    }
+
+    fn seal(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        plaintext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError> {
+        if out.len() != plaintext.len() + self.tag_len() {
+            return Err(UnknownCryptoError);
+        }
+        xchacha20poly1305::seal(
+            &chacha20::SecretKey::from_slice(key.unprotected_as_bytes())?,
+            &xchacha20::Nonce::from_slice(nonce)?,
+            plaintext,
+            Some(ad),
+            out,
+        )
+    }
+
+    fn open(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError> {
+        if ciphertext.len() != out.len() + self.tag_len() {
+            return Err(UnknownCryptoError);
+        }
+        xchacha20poly1305::open(
+            &chacha20::SecretKey::from_slice(key.unprotected_as_bytes())?,
+            &xchacha20::Nonce::from_slice(nonce)?,
+            ciphertext,
+            Some(ad),
+            out,
+        )
+    }
+}
+
+struct AesGcmBackend;
+
+impl AesGcmBackend {
+    fn cipher(key: &SecretKey) -> Result<aes_gcm::Aes256Gcm, UnknownCryptoError> {
+        aes_gcm::Aes256Gcm::new_from_slice(key.unprotected_as_bytes())
+            .map_err(|_| UnknownCryptoError)
+    }
+}
+
+impl CipherBackend for AesGcmBackend {
+    fn nonce_len(&self) -> usize {
+        12
+    }
+
+    fn tag_len(&self) -> usize {
+        16
+    }
+
+    fn seal(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        plaintext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError> {
+        if nonce.len() != self.nonce_len() || out.len() != plaintext.len() + self.tag_len() {
+            return Err(UnknownCryptoError);
+        }
+        let (body, tag) = out.split_at_mut(plaintext.len());
+        body.copy_from_slice(plaintext);
+        let computed = AesGcmBackend::cipher(key)?
+            .encrypt_in_place_detached(GenericArray::from_slice(nonce), ad, body)
+            .map_err(|_| UnknownCryptoError)?;
+        tag.copy_from_slice(&computed);
+        Ok(())
+    }
+
+    fn open(
+        &self,
+        key: &SecretKey,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        ad: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), UnknownCryptoError> {
+        if nonce.len() != self.nonce_len() || ciphertext.len() != out.len() + self.tag_len() {
+            return Err(UnknownCryptoError);
+        }
+        let (body, tag) = ciphertext.split_at(out.len());
+        out.copy_from_slice(body);
+        AesGcmBackend::cipher(key)?
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                ad,
+                out,
+                GenericArray::from_slice(tag),
+            )
+            .map_err(|_| UnknownCryptoError)
+    }
+}
@@ -1,33 +1,153 @@
+use super::error::VaultError;
+use super::header::VaultHeader;
+use super::stream::{StreamOpener, StreamSealer};
 use orion::aead;
+use orion::errors::UnknownCryptoError;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::SeekFrom;
-
-pub fn reset_file_cursor(file: &mut File) {
-    file.seek(SeekFrom::Start(0)).expect("Failed to seek");
-}
-
-// TODO: Make sure these functions return results
-pub fn encrypt_data(file: &mut File, key_ref: &aead::SecretKey) {
-    let mut buffer = Vec::new();
-    reset_file_cursor(file);
-    file.read_to_end(&mut buffer)
-        .expect("Error reading file to buffer");
-    let cipher_text = aead::seal(key_ref, &buffer).unwrap();
-    reset_file_cursor(file);
-    file.set_len(0).unwrap();
-    file.write_all(&cipher_text).unwrap();
-    reset_file_cursor(file);
-}
-
-/*
-*/
-pub fn decrypt_data(file: &mut File, key_ref: &aead::SecretKey) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    reset_file_cursor(file);
-    file.read_to_end(&mut buffer)
-        .expect("Error reading file to buffer");
-    let decrypted_data = aead::open(key_ref, &buffer).unwrap();
-    reset_file_cursor(file);
-    return decrypted_data;
+use std::io::{self, SeekFrom};
+use zeroize::Zeroizing;
+
+// A vault file is laid out as `header || body`, see `header.rs` for the
+// header and `stream.rs` for the chunked body. The fixed part of the header
+// is authenticated as associated data, its key slots by a MAC of their own,
+// so the slots can be replaced without touching the body. The nonce and tag
+// sizes depend on the cipher recorded in the header, see `cipher.rs`.
+//
+// Decrypted data is always handed out as `Zeroizing` so it is wiped once the
+// caller is done with it. The functions working on files report failures as
+// a `VaultError`, see `error.rs`.
+
+pub fn reset_file_cursor(file: &mut File) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0)).map(|_| ())
+}
+
+// Reads the rest of `file` into `buffer` after sizing it to fit, so the
+// buffer isn't grown by copying, which would leave stray copies of a
+// plaintext file behind
+fn read_exact_size(file: &mut File, buffer: &mut Vec<u8>) -> io::Result<()> {
+    let len = file.metadata()?.len() as usize;
+    buffer.reserve_exact(len + 1);
+    file.read_to_end(buffer).map(|_| ())
+}
+
+// The streams report failures as `io::Error`, which reading from memory can
+// only get from the cipher
+fn crypto_error(_: io::Error) -> UnknownCryptoError {
+    UnknownCryptoError
+}
+
+/// Encrypts `plaintext` into a complete vault file image.
+pub fn seal(
+    key_ref: &aead::SecretKey,
+    header: &VaultHeader,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, UnknownCryptoError> {
+    let mut sealer = StreamSealer::new(key_ref, header, Vec::new()).map_err(crypto_error)?;
+    sealer.write_all(plaintext).map_err(crypto_error)?;
+    sealer.finish().map_err(crypto_error)
+}
+
+/// Decrypts a vault file image produced by `seal`.
+pub fn open(
+    key_ref: &aead::SecretKey,
+    sealed: &[u8],
+) -> Result<(VaultHeader, Zeroizing<Vec<u8>>), UnknownCryptoError> {
+    let (header, header_len) = VaultHeader::parse(sealed)?;
+    let reader = &sealed[header_len..];
+    let mut opener =
+        StreamOpener::with_header_bytes(key_ref, reader, sealed[..header_len].to_vec())
+            .map_err(crypto_error)?;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(reader.len() + 1));
+    opener.read_to_end(&mut plaintext).map_err(crypto_error)?;
+    Ok((header, plaintext))
+}
+
+/// Seals `plaintext` and replaces the contents of `file` with the result,
+/// one chunk at a time.
+pub fn write_encrypted(
+    file: &mut File,
+    key_ref: &aead::SecretKey,
+    header: &VaultHeader,
+    plaintext: &[u8],
+) -> Result<(), VaultError> {
+    file.seek(SeekFrom::Start(0))?;
+    file.set_len(0)?;
+    let mut sealer = StreamSealer::new(key_ref, header, &mut *file)?;
+    sealer.write_all(plaintext)?;
+    sealer.finish()?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+/// Encrypts the plaintext in `file` in place.
+pub fn encrypt_data(
+    file: &mut File,
+    key_ref: &aead::SecretKey,
+    header: &VaultHeader,
+) -> Result<(), VaultError> {
+    let mut buffer = Zeroizing::new(Vec::new());
+    file.seek(SeekFrom::Start(0))?;
+    read_exact_size(file, &mut buffer)?;
+    write_encrypted(file, key_ref, header, &buffer)
+}
+
+/// Reads the header of a vault file without decrypting anything.
+pub fn read_header(file: &mut File) -> Result<VaultHeader, VaultError> {
+    file.seek(SeekFrom::Start(0))?;
+    let header_bytes = VaultHeader::read_bytes(file)?;
+    file.seek(SeekFrom::Start(0))?;
+    VaultHeader::parse(&header_bytes)
+        .map(|(header, _)| header)
+        .map_err(|_| VaultError::Corrupted)
+}
+
+/// Fails with `WrongKey` when `key_ref` is not the key the vault was sealed
+/// with, and with `Corrupted` when the file has been tampered with or cut
+/// short. The file is decrypted a chunk at a time, so only the plaintext is
+/// held in memory in full.
+pub fn decrypt_data(
+    file: &mut File,
+    key_ref: &aead::SecretKey,
+) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+    file.seek(SeekFrom::Start(0))?;
+    let len = file.metadata()?.len() as usize;
+    let header_bytes = VaultHeader::read_bytes(file)?;
+    let mut opener = StreamOpener::with_header_bytes(key_ref, &mut *file, header_bytes)?;
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(len + 1));
+    opener.read_to_end(&mut plaintext)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(plaintext)
+}
+
+/// Swaps the header of a vault image for `new_header`, leaving the body as
+/// it is. `data_key` has to check the current header's MAC, and the fixed
+/// part of both headers has to match, since the body is bound to it. Only
+/// the key slots can change this way.
+pub fn replace_header(
+    sealed: &[u8],
+    data_key: &aead::SecretKey,
+    new_header: &VaultHeader,
+) -> Result<Vec<u8>, UnknownCryptoError> {
+    let (header, header_len) = VaultHeader::parse(sealed)?;
+    VaultHeader::verify(&sealed[..header_len], data_key)?;
+    if header.fixed_bytes() != new_header.fixed_bytes() {
+        return Err(UnknownCryptoError);
+    }
+    let mut replaced = new_header.to_bytes(data_key)?;
+    replaced.extend_from_slice(&sealed[header_len..]);
+    Ok(replaced)
+}
+
+/// Re-encrypts a vault image under `new_key` and `new_header`, which may use
+/// another cipher. Fails without producing anything if `old_key` doesn't open
+/// the vault.
+pub fn rekey(
+    sealed: &[u8],
+    old_key: &aead::SecretKey,
+    new_key: &aead::SecretKey,
+    new_header: &VaultHeader,
+) -> Result<Vec<u8>, UnknownCryptoError> {
+    let (_, plaintext) = open(old_key, sealed)?;
+    seal(new_key, new_header, &plaintext)
 }
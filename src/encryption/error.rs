@@ -0,0 +1,44 @@
+use std::io;
+use thiserror::Error;
+
+/// Why a vault couldn't be read or written.
+///
+/// An AEAD can't tell a wrong key from a tampered file, so a vault whose
+/// header MAC or first chunk fails to check is reported as `WrongKey`, and
+/// one that fails further in, was cut short or has a broken header as
+/// `Corrupted`. A password or keyfile that no key slot takes is a wrong key
+/// too.
+#[derive(Debug, Error)]
+pub enum VaultError {
+    #[error("the master password, keyfile or key doesn't open the password store")]
+    WrongKey,
+    #[error("the password store is corrupted")]
+    Corrupted,
+    #[error("couldn't encrypt the password store")]
+    Encryption,
+    #[error("error accessing the password store: {0}")]
+    Io(io::Error),
+    #[error("error parsing the password store: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+impl VaultError {
+    // The streams can only fail with an io::Error, so the other variants are
+    // carried inside one
+    pub(crate) fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
+impl From<io::Error> for VaultError {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<VaultError>()) {
+            return *e.into_inner().unwrap().downcast::<VaultError>().unwrap();
+        }
+        match e.kind() {
+            // The file ended before its header did
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => VaultError::Corrupted,
+            _ => VaultError::Io(e),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+pub use super::cipher::Cipher;
+use super::keyslot::{KeySlot, SlotKind};
+use super::recovery::RecoveryKey;
+use orion::errors::UnknownCryptoError;
+use orion::{aead, auth};
+use std::convert::TryInto;
+use std::io::{self, Read};
+
+// On-disk layout of the header, all integers little endian:
+//
+//   magic       4 bytes  "ARLK"
+//   version     u16
+//   cipher      u8       Cipher id
+//   chunk_size  u32      plaintext bytes per chunk of the body
+//   vault_id    16 bytes random, set when the vault is created
+//   slot_count  u8
+//   slots       slot_count key slots, see `keyslot.rs`
+//   slots_mac   32 bytes BLAKE2b over everything before it
+//
+// The part up to and including vault_id is fixed for the life of the vault
+// and is passed to the AEAD as associated data, so changing it makes the
+// vault fail to open. The key slots change whenever a password or keyfile
+// does, so they are authenticated on their own by slots_mac, keyed from the
+// data key. That way a slot change only rewrites the header and leaves the
+// body as it is. Stores from before the header are only read to migrate
+// them, see `legacy.rs`.
+
+pub const MAGIC: &[u8; 4] = b"ARLK";
+pub const FORMAT_VERSION: u16 = 5;
+
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+// Caps how much a chunk can make the reader buffer
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+const PREFIX_LEN: usize = 4 + 2;
+
+pub const VAULT_ID_LEN: usize = 16;
+
+// cipher, chunk_size and vault_id
+const FIXED_LEN: usize = 1 + 4 + VAULT_ID_LEN;
+
+// The bytes every chunk of the body takes as associated data
+pub(crate) const FIXED_PART_LEN: usize = PREFIX_LEN + FIXED_LEN;
+
+const MAC_LEN: usize = 32;
+
+// Derives the slots_mac key from the data key, so it isn't used as is for
+// two things
+const MAC_KEY_CONTEXT: &[u8] = b"arustylock key slot table";
+
+/// Describes how a vault file was encrypted and holds the key slots its data
+/// key is wrapped under.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VaultHeader {
+    pub cipher: Cipher,
+    pub chunk_size: u32,
+    pub vault_id: [u8; VAULT_ID_LEN],
+    pub slots: Vec<KeySlot>,
+}
+
+// The key of slots_mac
+fn mac_key(data_key: &aead::SecretKey) -> Result<auth::SecretKey, UnknownCryptoError> {
+    let key = auth::SecretKey::from_slice(data_key.unprotected_as_bytes())?;
+    let mac_key = auth::authenticate(&key, MAC_KEY_CONTEXT)?;
+    auth::SecretKey::from_slice(mac_key.unprotected_as_bytes())
+}
+
+// Appends the next `len` bytes of `reader` to `bytes`
+pub(crate) fn read_more<R: Read>(
+    reader: &mut R,
+    bytes: &mut Vec<u8>,
+    len: usize,
+) -> io::Result<()> {
+    let start = bytes.len();
+    bytes.resize(start + len, 0);
+    reader.read_exact(&mut bytes[start..])
+}
+
+impl Default for VaultHeader {
+    /// A header for a new vault. It has no key slots yet.
+    fn default() -> Self {
+        VaultHeader::new(Cipher::XChaCha20Poly1305)
+    }
+}
+
+impl VaultHeader {
+    /// A header for a new vault sealed with `cipher`, with a fresh vault ID.
+    /// It has no key slots yet.
+    pub fn new(cipher: Cipher) -> VaultHeader {
+        let mut vault_id = [0u8; VAULT_ID_LEN];
+        orion::util::secure_rand_bytes(&mut vault_id).expect("Couldn't generate a vault ID");
+        VaultHeader {
+            cipher,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            vault_id,
+            slots: Vec::new(),
+        }
+    }
+
+    /// Whether `bytes` starts with the vault magic.
+    pub fn has_magic(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// The format version of the vault in `bytes`, if it has a header.
+    pub fn version_of(bytes: &[u8]) -> Option<u16> {
+        if bytes.len() < PREFIX_LEN || !VaultHeader::has_magic(bytes) {
+            return None;
+        }
+        Some(u16::from_le_bytes(bytes[4..6].try_into().unwrap()))
+    }
+
+    /// The part of the header that never changes, which the body is bound
+    /// to.
+    pub fn fixed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_PART_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.push(self.cipher.id());
+        bytes.extend_from_slice(&self.chunk_size.to_le_bytes());
+        bytes.extend_from_slice(&self.vault_id);
+        bytes
+    }
+
+    /// The header as written to the file. `data_key` keys the MAC over the
+    /// key slots, so it has to be the key they wrap.
+    pub fn to_bytes(&self, data_key: &aead::SecretKey) -> Result<Vec<u8>, UnknownCryptoError> {
+        let mut bytes = self.fixed_bytes();
+        bytes.push(self.slots.len() as u8);
+        for slot in &self.slots {
+            slot.write(&mut bytes);
+        }
+        let mac = auth::authenticate(&mac_key(data_key)?, &bytes)?;
+        bytes.extend_from_slice(mac.unprotected_as_bytes());
+        Ok(bytes)
+    }
+
+    /// Checks the MAC at the end of `header_bytes`, a header as written by
+    /// `to_bytes`. A wrong `data_key` fails just like a changed header does.
+    pub fn verify(
+        header_bytes: &[u8],
+        data_key: &aead::SecretKey,
+    ) -> Result<(), UnknownCryptoError> {
+        let mac_start = header_bytes
+            .len()
+            .checked_sub(MAC_LEN)
+            .ok_or(UnknownCryptoError)?;
+        let mac = auth::Tag::from_slice(&header_bytes[mac_start..])?;
+        auth::authenticate_verify(&mac, &mac_key(data_key)?, &header_bytes[..mac_start])
+    }
+
+    /// Parses the header at the start of `bytes`, returning it together with
+    /// its length in bytes. Its MAC is left for `verify` to check, which
+    /// takes the data key.
+    pub fn parse(bytes: &[u8]) -> Result<(VaultHeader, usize), UnknownCryptoError> {
+        if VaultHeader::version_of(bytes) != Some(FORMAT_VERSION)
+            || bytes.len() < FIXED_PART_LEN + 1
+        {
+            return Err(UnknownCryptoError);
+        }
+        let cipher = Cipher::from_id(bytes[PREFIX_LEN]).ok_or(UnknownCryptoError)?;
+        let chunk_size =
+            u32::from_le_bytes(bytes[PREFIX_LEN + 1..PREFIX_LEN + 5].try_into().unwrap());
+        let vault_id = bytes[PREFIX_LEN + 5..FIXED_PART_LEN].try_into().unwrap();
+        let slot_count = bytes[FIXED_PART_LEN] as usize;
+        if slot_count == 0 || chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(UnknownCryptoError);
+        }
+
+        let mut len = FIXED_PART_LEN + 1;
+        let mut slots = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            let (slot, slot_len) = KeySlot::parse(&bytes[len..])?;
+            slots.push(slot);
+            len += slot_len;
+        }
+        len += MAC_LEN;
+        if bytes.len() < len {
+            return Err(UnknownCryptoError);
+        }
+        let header = VaultHeader {
+            cipher,
+            chunk_size,
+            vault_id,
+            slots,
+        };
+        Ok((header, len))
+    }
+
+    /// Reads just the bytes of the header at the start of `reader`, leaving
+    /// it at the start of the body. The bytes still have to be checked with
+    /// `parse`.
+    pub fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; PREFIX_LEN];
+        reader.read_exact(&mut bytes)?;
+        if VaultHeader::version_of(&bytes) != Some(FORMAT_VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a vault header",
+            ));
+        }
+        read_more(reader, &mut bytes, FIXED_LEN + 1)?;
+        for _ in 0..bytes[bytes.len() - 1] {
+            KeySlot::read_bytes(reader, &mut bytes)?;
+        }
+        read_more(reader, &mut bytes, MAC_LEN)?;
+        Ok(bytes)
+    }
+
+    /// Whether some slot takes a keyfile.
+    pub fn uses_keyfile(&self) -> bool {
+        self.slots.iter().any(|slot| slot.kind.needs_keyfile())
+    }
+
+    /// Whether some slot can be tried with the factors at hand. Recovery key
+    /// slots don't count, they are only tried with `unlock_with_recovery_key`.
+    pub fn accepts(&self, has_password: bool, has_keyfile: bool) -> bool {
+        self.slots.iter().any(|slot| {
+            slot.kind != SlotKind::RecoveryKey && slot.accepts(has_password, has_keyfile)
+        })
+    }
+
+    /// Tries every slot that takes these factors, returning the data key and
+    /// the index of the slot that opened.
+    pub fn unlock(
+        &self,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<(aead::SecretKey, usize), UnknownCryptoError> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| {
+                slot.kind != SlotKind::RecoveryKey
+                    && slot.accepts(!password.is_empty(), keyfile.is_some())
+            })
+            .find_map(|(i, slot)| {
+                slot.unlock(self.cipher, password, keyfile)
+                    .ok()
+                    .map(|key| (key, i))
+            })
+            .ok_or(UnknownCryptoError)
+    }
+
+    pub fn has_recovery_key(&self) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.kind == SlotKind::RecoveryKey)
+    }
+
+    /// Tries the recovery key slots, returning the data key and the index of
+    /// the slot that opened.
+    pub fn unlock_with_recovery_key(
+        &self,
+        recovery_key: &RecoveryKey,
+    ) -> Result<(aead::SecretKey, usize), UnknownCryptoError> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.kind == SlotKind::RecoveryKey)
+            .find_map(|(i, slot)| {
+                slot.unlock(self.cipher, &recovery_key.secret(), None)
+                    .ok()
+                    .map(|key| (key, i))
+            })
+            .ok_or(UnknownCryptoError)
+    }
+}
@@ -0,0 +1,35 @@
+use orion::errors::UnknownCryptoError;
+use orion::{aead, hash, kdf};
+use zeroize::Zeroizing;
+
+// Argon2i cost parameters used for new vaults. orion's KDF takes the memory
+// cost in KiB, so this is 64 MiB.
+pub const DEFAULT_ITERATIONS: u32 = 3;
+pub const DEFAULT_MEMORY_KIB: u32 = 1 << 16;
+
+pub const SALT_LEN: usize = 16;
+const KEY_LEN: u32 = 32;
+
+/// Derives the vault key from the master password using orion's Argon2i KDF.
+///
+/// When a keyfile is given, a BLAKE2b hash of its contents is appended to the
+/// password before it goes into Argon2, so both are needed to get the key.
+pub fn derive_key(
+    password: &str,
+    keyfile: Option<&[u8]>,
+    salt: &kdf::Salt,
+    iterations: u32,
+    memory_kib: u32,
+) -> Result<aead::SecretKey, UnknownCryptoError> {
+    let mut secret = Zeroizing::new(Vec::with_capacity(password.len() + 64));
+    secret.extend_from_slice(password.as_bytes());
+    if let Some(contents) = keyfile {
+        if contents.is_empty() {
+            return Err(UnknownCryptoError);
+        }
+        secret.extend_from_slice(hash::digest(contents)?.as_ref());
+    }
+    let password = kdf::Password::from_slice(&secret)?;
+    let derived = kdf::derive_key(&password, salt, iterations, memory_kib, KEY_LEN)?;
+    aead::SecretKey::from_slice(derived.unprotected_as_bytes())
+}
@@ -0,0 +1,226 @@
+use super::cipher::Cipher;
+use super::header::read_more;
+use super::kdf::{self, DEFAULT_ITERATIONS, DEFAULT_MEMORY_KIB, SALT_LEN};
+use orion::aead;
+use orion::errors::UnknownCryptoError;
+use std::convert::TryInto;
+use std::io::{self, Read};
+
+// A key slot wraps the vault's random data key under a key derived from one
+// unlock secret. Its on-disk layout, integers little endian:
+//
+//   kind         u8       SlotKind id
+//   kdf          u8       KdfAlgorithm id
+//   iterations   u32
+//   memory       u32      KiB
+//   salt_len     u8
+//   salt         salt_len bytes
+//   wrapped_len  u8
+//   wrapped_key  wrapped_len bytes, the data key sealed under the slot key
+//                with the vault's cipher, as nonce || ciphertext || tag
+
+const FIXED_LEN: usize = 1 + 1 + 4 + 4 + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    Password,
+    PasswordAndKeyfile,
+    Recovery,
+    Keyfile,
+    RecoveryKey,
+}
+
+impl SlotKind {
+    fn id(self) -> u8 {
+        match self {
+            SlotKind::Password => 1,
+            SlotKind::PasswordAndKeyfile => 2,
+            SlotKind::Recovery => 3,
+            SlotKind::Keyfile => 4,
+            SlotKind::RecoveryKey => 5,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<SlotKind> {
+        match id {
+            1 => Some(SlotKind::Password),
+            2 => Some(SlotKind::PasswordAndKeyfile),
+            3 => Some(SlotKind::Recovery),
+            4 => Some(SlotKind::Keyfile),
+            5 => Some(SlotKind::RecoveryKey),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SlotKind::Password => "master password",
+            SlotKind::PasswordAndKeyfile => "master password and keyfile",
+            SlotKind::Recovery => "recovery passphrase",
+            SlotKind::Keyfile => "keyfile",
+            SlotKind::RecoveryKey => "recovery key",
+        }
+    }
+
+    pub fn needs_password(self) -> bool {
+        self != SlotKind::Keyfile
+    }
+
+    pub fn needs_keyfile(self) -> bool {
+        matches!(self, SlotKind::PasswordAndKeyfile | SlotKind::Keyfile)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    Argon2i,
+}
+
+impl KdfAlgorithm {
+    fn id(self) -> u8 {
+        match self {
+            KdfAlgorithm::Argon2i => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<KdfAlgorithm> {
+        match id {
+            1 => Some(KdfAlgorithm::Argon2i),
+            _ => None,
+        }
+    }
+}
+
+/// Cost parameters for deriving a slot key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    pub iterations: u32,
+    pub memory_kib: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            iterations: DEFAULT_ITERATIONS,
+            memory_kib: DEFAULT_MEMORY_KIB,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySlot {
+    pub kind: SlotKind,
+    pub kdf: KdfAlgorithm,
+    pub params: KdfParams,
+    pub salt: Vec<u8>,
+    pub wrapped_key: Vec<u8>,
+}
+
+impl KeySlot {
+    /// Wraps `data_key` under a key derived from `password` and `keyfile` with
+    /// a fresh random salt, using the vault's cipher.
+    pub fn new(
+        kind: SlotKind,
+        cipher: Cipher,
+        params: &KdfParams,
+        password: &str,
+        keyfile: Option<&[u8]>,
+        data_key: &aead::SecretKey,
+    ) -> Result<KeySlot, UnknownCryptoError> {
+        let mut salt = vec![0u8; SALT_LEN];
+        orion::util::secure_rand_bytes(&mut salt)?;
+        let mut slot = KeySlot {
+            kind,
+            kdf: KdfAlgorithm::Argon2i,
+            params: *params,
+            salt,
+            wrapped_key: Vec::new(),
+        };
+        let slot_key = slot.derive_key(password, keyfile)?;
+        slot.wrapped_key = cipher.seal(&slot_key, data_key.unprotected_as_bytes(), &[])?;
+        Ok(slot)
+    }
+
+    /// Whether this slot can be tried with the factors at hand.
+    pub fn accepts(&self, has_password: bool, has_keyfile: bool) -> bool {
+        self.kind.needs_password() == has_password && self.kind.needs_keyfile() == has_keyfile
+    }
+
+    /// Recovers the data key, failing if the secrets are wrong.
+    pub fn unlock(
+        &self,
+        cipher: Cipher,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<aead::SecretKey, UnknownCryptoError> {
+        if !self.accepts(!password.is_empty(), keyfile.is_some()) {
+            return Err(UnknownCryptoError);
+        }
+        let slot_key = self.derive_key(password, keyfile)?;
+        let data_key = cipher.open(&slot_key, &self.wrapped_key, &[])?;
+        aead::SecretKey::from_slice(&data_key)
+    }
+
+    fn derive_key(
+        &self,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<aead::SecretKey, UnknownCryptoError> {
+        match self.kdf {
+            KdfAlgorithm::Argon2i => kdf::derive_key(
+                password,
+                keyfile,
+                &orion::kdf::Salt::from_slice(&self.salt)?,
+                self.params.iterations,
+                self.params.memory_kib,
+            ),
+        }
+    }
+
+    pub(crate) fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.kind.id());
+        bytes.push(self.kdf.id());
+        bytes.extend_from_slice(&self.params.iterations.to_le_bytes());
+        bytes.extend_from_slice(&self.params.memory_kib.to_le_bytes());
+        bytes.push(self.salt.len() as u8);
+        bytes.extend_from_slice(&self.salt);
+        bytes.push(self.wrapped_key.len() as u8);
+        bytes.extend_from_slice(&self.wrapped_key);
+    }
+
+    // Appends the raw bytes of the slot at the start of `reader` to `bytes`
+    pub(crate) fn read_bytes<R: Read>(reader: &mut R, bytes: &mut Vec<u8>) -> io::Result<()> {
+        read_more(reader, bytes, FIXED_LEN)?;
+        read_more(reader, bytes, bytes[bytes.len() - 1] as usize + 1)?;
+        read_more(reader, bytes, bytes[bytes.len() - 1] as usize)
+    }
+
+    /// Parses the slot at the start of `bytes`, returning it together with its
+    /// length in bytes.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<(KeySlot, usize), UnknownCryptoError> {
+        if bytes.len() < FIXED_LEN {
+            return Err(UnknownCryptoError);
+        }
+        let kind = SlotKind::from_id(bytes[0]).ok_or(UnknownCryptoError)?;
+        let kdf = KdfAlgorithm::from_id(bytes[1]).ok_or(UnknownCryptoError)?;
+        let params = KdfParams {
+            iterations: u32::from_le_bytes(bytes[2..6].try_into().unwrap()),
+            memory_kib: u32::from_le_bytes(bytes[6..10].try_into().unwrap()),
+        };
+        let salt_end = FIXED_LEN + bytes[10] as usize;
+        let wrapped_len = *bytes.get(salt_end).ok_or(UnknownCryptoError)? as usize;
+        let len = salt_end + 1 + wrapped_len;
+        if bytes.len() < len {
+            return Err(UnknownCryptoError);
+        }
+
+        let slot = KeySlot {
+            kind,
+            kdf,
+            params,
+            salt: bytes[FIXED_LEN..salt_end].to_vec(),
+            wrapped_key: bytes[salt_end + 1..len].to_vec(),
+        };
+        Ok((slot, len))
+    }
+}
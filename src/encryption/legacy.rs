@@ -0,0 +1,19 @@
+use super::header::VaultHeader;
+use orion::aead;
+use orion::errors::UnknownCryptoError;
+use zeroize::Zeroizing;
+
+// Before master passwords every store was sealed with this compiled-in key
+// and written without a header. It is only kept around to migrate those
+// stores to the current format.
+pub const LEGACY_KEY: &[u8; 32] = b"qaz123WSX$%^edcplm098IJN765uhbZQ";
+
+/// Whether `bytes` is a store written before the vault header existed.
+pub fn is_legacy(bytes: &[u8]) -> bool {
+    !VaultHeader::has_magic(bytes)
+}
+
+/// Decrypts a pre-header store with the legacy key.
+pub fn open_legacy(sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+    aead::open(&aead::SecretKey::from_slice(LEGACY_KEY)?, sealed).map(Zeroizing::new)
+}
@@ -1 +1,11 @@
+pub mod cipher;
+#[allow(clippy::module_inception)]
 pub mod encryption;
+pub mod error;
+pub mod header;
+pub mod kdf;
+pub mod keyslot;
+pub mod legacy;
+pub mod recovery;
+pub mod shamir;
+pub mod stream;
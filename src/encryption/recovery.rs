@@ -0,0 +1,220 @@
+use super::shamir::{self, ShamirError, Share};
+use orion::errors::UnknownCryptoError;
+use orion::hash;
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+// A recovery key is 16 random bytes written down as one word per byte,
+// followed by a checksum word taken from a hash of the bytes. Every word in
+// the list starts with a different four letters, so a smudged word can
+// usually still be made out.
+//
+// A recovery key can also be split into Shamir shares. A share is written
+// the same way, as its threshold, its index and its 16 bytes, followed by a
+// checksum word.
+
+pub const RECOVERY_KEY_LEN: usize = 16;
+pub const PHRASE_WORDS: usize = RECOVERY_KEY_LEN + 1;
+pub const SHARE_WORDS: usize = 2 + RECOVERY_KEY_LEN + 1;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+const WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "adult", "agent", "alarm", "album", "alley", "amber", "anchor",
+    "angle", "ankle", "apple", "apron", "arena", "armor", "arrow", "atlas", "attic", "autumn",
+    "avenue", "bacon", "badge", "bagel", "baker", "bamboo", "banjo", "barrel", "basket", "beach",
+    "beaver", "bench", "berry", "bishop", "blossom", "boiler", "bonus", "border", "bottle",
+    "bounce", "bracket", "breeze", "brick", "bronze", "bucket", "buffalo", "butter", "cabin",
+    "cactus", "camel", "candle", "canoe", "carpet", "castle", "cattle", "cellar", "cement",
+    "census", "chalk", "cherry", "circus", "clock", "cobalt", "coffee", "comet", "copper", "coral",
+    "cotton", "cowboy", "crater", "curtain", "cushion", "dagger", "daisy", "dancer", "debate",
+    "decade", "denim", "desert", "dinner", "doctor", "domino", "donkey", "dragon", "dream", "drum",
+    "eagle", "earth", "easel", "echo", "editor", "elbow", "ember", "empire", "engine", "eraser",
+    "error", "escape", "ethics", "exile", "fabric", "falcon", "family", "farmer", "feather",
+    "fence", "ferry", "fiddle", "figure", "finger", "fiscal", "flame", "flute", "forest", "fossil",
+    "fox", "frame", "frost", "fruit", "funnel", "galaxy", "garden", "gentle", "giant", "ginger",
+    "globe", "goblet", "golden", "gospel", "grape", "guitar", "gutter", "hammer", "harbor",
+    "hazel", "helmet", "hermit", "hockey", "honey", "hornet", "hotel", "humble", "igloo", "indigo",
+    "inmate", "island", "ivory", "jacket", "jaguar", "jelly", "jester", "jigsaw", "jockey",
+    "journal", "jungle", "kettle", "kidney", "kitten", "koala", "ladder", "lagoon", "laptop",
+    "lemon", "letter", "lizard", "locket", "lumber", "lunar", "magnet", "mammal", "mango",
+    "marble", "meadow", "melon", "mirror", "monkey", "mosaic", "muffin", "museum", "napkin",
+    "nectar", "needle", "nephew", "nickel", "noodle", "novel", "number", "nutmeg", "oasis",
+    "ocean", "olive", "onion", "opera", "orange", "orbit", "orchid", "otter", "oven", "oyster",
+    "paddle", "palace", "panda", "parrot", "peanut", "pencil", "pepper", "piano", "pickle",
+    "pillow", "pirate", "planet", "pocket", "potato", "puzzle", "quarry", "quiver", "rabbit",
+    "radar", "raisin", "ranch", "raven", "record", "ribbon", "rocket", "rubber", "saddle",
+    "salmon", "sandal", "saucer", "scarf", "shadow", "silver", "sister", "skate", "socket",
+    "spider", "sponge", "statue", "sugar", "summer", "tablet", "tennis", "tiger", "toast",
+    "tomato", "tunnel", "turtle", "unicorn", "velvet", "violin", "volcano", "wagon", "walnut",
+    "window", "yogurt", "zebra",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecoveryKeyError {
+    #[error(
+        "a recovery key has {} words and a share {}, got {0}",
+        PHRASE_WORDS,
+        SHARE_WORDS
+    )]
+    WrongLength(usize),
+    #[error("'{0}' is not a recovery key word")]
+    UnknownWord(String),
+    #[error("there is a typo, the checksum word doesn't match")]
+    BadChecksum,
+}
+
+pub struct RecoveryKey {
+    bytes: Vec<u8>,
+}
+
+impl Drop for RecoveryKey {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
+/// One of the shares a recovery key was split into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryShare {
+    pub threshold: u8,
+    pub share: Share,
+}
+
+/// What was typed in when asked for a recovery key, told apart by length.
+pub enum RecoveryPhrase {
+    Key(RecoveryKey),
+    Share(RecoveryShare),
+}
+
+impl RecoveryPhrase {
+    /// Reads a recovery key or share back from its words. Case, extra
+    /// whitespace and dashes between words are ignored.
+    pub fn parse(phrase: &str) -> Result<RecoveryPhrase, RecoveryKeyError> {
+        let bytes = from_words(phrase)?;
+        match bytes.len() {
+            RECOVERY_KEY_LEN => Ok(RecoveryPhrase::Key(RecoveryKey {
+                bytes: bytes.to_vec(),
+            })),
+            _ => Ok(RecoveryPhrase::Share(RecoveryShare {
+                threshold: bytes[0],
+                share: Share {
+                    x: bytes[1],
+                    y: bytes[2..].to_vec(),
+                },
+            })),
+        }
+    }
+}
+
+impl RecoveryKey {
+    pub fn generate() -> Result<RecoveryKey, UnknownCryptoError> {
+        let mut bytes = vec![0u8; RECOVERY_KEY_LEN];
+        orion::util::secure_rand_bytes(&mut bytes)?;
+        Ok(RecoveryKey { bytes })
+    }
+
+    /// Reads a recovery key back from its words, see `RecoveryPhrase::parse`.
+    pub fn parse(phrase: &str) -> Result<RecoveryKey, RecoveryKeyError> {
+        match RecoveryPhrase::parse(phrase)? {
+            RecoveryPhrase::Key(key) => Ok(key),
+            RecoveryPhrase::Share(_) => Err(RecoveryKeyError::WrongLength(SHARE_WORDS)),
+        }
+    }
+
+    /// The words to write down, checksum word last.
+    pub fn words(&self) -> Vec<&'static str> {
+        to_words(&self.bytes)
+    }
+
+    /// The secret the recovery key slot is derived from.
+    pub fn secret(&self) -> Zeroizing<String> {
+        let mut secret = Zeroizing::new(String::with_capacity(2 * self.bytes.len()));
+        for byte in &self.bytes {
+            secret.push(char::from(HEX_DIGITS[(byte >> 4) as usize]));
+            secret.push(char::from(HEX_DIGITS[(byte & 0xf) as usize]));
+        }
+        secret
+    }
+
+    /// Splits the recovery key into `count` shares, any `threshold` of which
+    /// give it back.
+    pub fn split(&self, threshold: u8, count: u8) -> Result<Vec<RecoveryShare>, ShamirError> {
+        Ok(shamir::split(&self.bytes, threshold, count)?
+            .into_iter()
+            .map(|share| RecoveryShare { threshold, share })
+            .collect())
+    }
+
+    /// Puts a recovery key back together from enough shares of one split.
+    pub fn combine(shares: &[RecoveryShare]) -> Result<RecoveryKey, ShamirError> {
+        let threshold = match shares.first() {
+            Some(share) => share.threshold,
+            None => return Err(ShamirError::NotEnoughShares { needed: 2, got: 0 }),
+        };
+        if shares.iter().any(|share| share.threshold != threshold) {
+            return Err(ShamirError::MismatchedShares);
+        }
+        if shares.len() < threshold as usize {
+            return Err(ShamirError::NotEnoughShares {
+                needed: threshold,
+                got: shares.len(),
+            });
+        }
+        let shares: Vec<Share> = shares.iter().map(|share| share.share.clone()).collect();
+        let recovery_key = RecoveryKey {
+            bytes: shamir::combine(&shares)?,
+        };
+        if recovery_key.bytes.len() != RECOVERY_KEY_LEN {
+            return Err(ShamirError::MismatchedShares);
+        }
+        Ok(recovery_key)
+    }
+}
+
+impl RecoveryShare {
+    /// The words to write down, checksum word last.
+    pub fn words(&self) -> Vec<&'static str> {
+        let mut bytes = Zeroizing::new(vec![self.threshold, self.share.x]);
+        bytes.extend_from_slice(&self.share.y);
+        to_words(&bytes)
+    }
+}
+
+fn to_words(bytes: &[u8]) -> Vec<&'static str> {
+    bytes
+        .iter()
+        .chain(std::iter::once(&checksum_of(bytes)))
+        .map(|byte| WORDS[*byte as usize])
+        .collect()
+}
+
+// Decodes a recovery key or share, dropping the checksum word once it has
+// been checked
+fn from_words(phrase: &str) -> Result<Zeroizing<Vec<u8>>, RecoveryKeyError> {
+    let phrase = Zeroizing::new(phrase.to_lowercase());
+    let words: Vec<&str> = phrase
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() != PHRASE_WORDS && words.len() != SHARE_WORDS {
+        return Err(RecoveryKeyError::WrongLength(words.len()));
+    }
+
+    let mut bytes = Zeroizing::new(Vec::with_capacity(words.len()));
+    for word in words {
+        match WORDS.iter().position(|w| *w == word) {
+            Some(index) => bytes.push(index as u8),
+            None => return Err(RecoveryKeyError::UnknownWord(word.to_string())),
+        }
+    }
+    let checksum = bytes.pop().unwrap();
+    if checksum != checksum_of(&bytes) {
+        return Err(RecoveryKeyError::BadChecksum);
+    }
+    Ok(bytes)
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    hash::digest(bytes).unwrap().as_ref()[0]
+}
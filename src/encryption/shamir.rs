@@ -0,0 +1,127 @@
+use orion::errors::UnknownCryptoError;
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+// Shamir's secret sharing over GF(2^8). Every byte of the secret is the
+// constant term of its own random polynomial of degree threshold - 1, and
+// share x holds the value of each polynomial at x. Any `threshold` shares
+// give back the secret by interpolating at 0, fewer say nothing about it.
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ShamirError {
+    #[error("can't split into {count} shares with a threshold of {threshold}")]
+    InvalidThreshold { threshold: u8, count: u8 },
+    #[error("{needed} shares are needed, got {got}")]
+    NotEnoughShares { needed: u8, got: usize },
+    #[error("the same share was entered twice")]
+    DuplicateShare,
+    #[error("the shares come from different splits")]
+    MismatchedShares,
+    #[error("couldn't generate random coefficients")]
+    Random(#[from] UnknownCryptoError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub x: u8,
+    pub y: Vec<u8>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.y.zeroize();
+    }
+}
+
+// Multiplication modulo the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the inverse of a, since the multiplicative group has order 255
+fn inverse(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = mul(result, a);
+    }
+    result
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which recover it.
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>, ShamirError> {
+    if threshold < 2 || threshold > count {
+        return Err(ShamirError::InvalidThreshold { threshold, count });
+    }
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|x| Share {
+            x,
+            y: Vec::with_capacity(secret.len()),
+        })
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for byte in secret {
+        coefficients[0] = *byte;
+        orion::util::secure_rand_bytes(&mut coefficients[1..])?;
+        for share in shares.iter_mut() {
+            // Horner's rule, highest coefficient first
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, coefficient| mul(acc, share.x) ^ coefficient);
+            share.y.push(y);
+        }
+    }
+    Ok(shares)
+}
+
+/// Recovers the secret from shares of the same split. The caller makes sure
+/// there are at least as many as the threshold, extra shares don't hurt.
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, ShamirError> {
+    let len = match shares.first() {
+        Some(share) => share.y.len(),
+        None => return Err(ShamirError::NotEnoughShares { needed: 1, got: 0 }),
+    };
+    for (i, share) in shares.iter().enumerate() {
+        if share.x == 0 || share.y.len() != len {
+            return Err(ShamirError::MismatchedShares);
+        }
+        if shares[..i].iter().any(|other| other.x == share.x) {
+            return Err(ShamirError::DuplicateShare);
+        }
+    }
+
+    // Lagrange basis polynomials evaluated at 0; subtraction is xor
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            shares
+                .iter()
+                .filter(|other| other.x != share.x)
+                .fold(1, |weight, other| {
+                    mul(weight, mul(other.x, inverse(other.x ^ share.x)))
+                })
+        })
+        .collect();
+    Ok((0..len)
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0, |secret, (share, weight)| {
+                    secret ^ mul(share.y[i], *weight)
+                })
+        })
+        .collect())
+}
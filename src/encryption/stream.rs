@@ -0,0 +1,283 @@
+use super::cipher::CipherBackend;
+use super::error::VaultError;
+use super::header::{VaultHeader, FIXED_PART_LEN};
+use orion::aead::SecretKey;
+use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
+
+// The body of a vault is split into chunks that are sealed one at
+// a time, so a vault can be read and written without holding all of it in
+// memory. It follows the STREAM construction:
+//
+//   nonce_prefix  nonce_len - 5 random bytes
+//   chunks        each chunk_size bytes of plaintext sealed with the nonce
+//                 nonce_prefix || counter (u32 big endian) || last (u8)
+//
+// Every chunk but the last holds exactly chunk_size bytes, the last one
+// fewer (possibly none) and is the only one sealed with `last` set to 1. The
+// counter stops chunks from being reordered or dropped, and since only the
+// last chunk can open with `last` set, cutting the file short at any point
+// fails to open. Every chunk takes the fixed part of the header as associated
+// data, the key slots are covered by the header's own MAC instead.
+
+const COUNTER_LEN: usize = 4;
+
+fn chunk_nonce(prefix: &[u8], counter: u32, last: bool) -> Vec<u8> {
+    let mut nonce = Vec::with_capacity(prefix.len() + COUNTER_LEN + 1);
+    nonce.extend_from_slice(prefix);
+    nonce.extend_from_slice(&counter.to_be_bytes());
+    nonce.push(last as u8);
+    nonce
+}
+
+/// Encrypts whatever is written to it into a vault, one chunk at a time.
+/// Call `finish` once everything has been written, or the vault is left
+/// without its last chunk and won't open.
+pub struct StreamSealer<'a, W: Write> {
+    writer: W,
+    key: &'a SecretKey,
+    backend: &'static dyn CipherBackend,
+    fixed_bytes: Vec<u8>,
+    prefix: Vec<u8>,
+    counter: u32,
+    chunk_size: usize,
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl<'a, W: Write> StreamSealer<'a, W> {
+    /// Writes the header and nonce prefix to `writer`.
+    pub fn new(
+        key: &'a SecretKey,
+        header: &VaultHeader,
+        mut writer: W,
+    ) -> io::Result<StreamSealer<'a, W>> {
+        let backend = header.cipher.backend();
+        let header_bytes = header
+            .to_bytes(key)
+            .map_err(|_| VaultError::Encryption.into_io())?;
+        let mut prefix = vec![0u8; backend.nonce_len() - COUNTER_LEN - 1];
+        orion::util::secure_rand_bytes(&mut prefix)
+            .map_err(|_| VaultError::Encryption.into_io())?;
+        writer.write_all(&header_bytes)?;
+        writer.write_all(&prefix)?;
+
+        let chunk_size = header.chunk_size as usize;
+        Ok(StreamSealer {
+            writer,
+            key,
+            backend,
+            fixed_bytes: header.fixed_bytes(),
+            prefix,
+            counter: 0,
+            chunk_size,
+            buffer: Zeroizing::new(Vec::with_capacity(chunk_size)),
+        })
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(&self.prefix, self.counter, last);
+        let mut sealed = vec![0u8; self.buffer.len() + self.backend.tag_len()];
+        self.backend
+            .seal(
+                self.key,
+                &nonce,
+                &self.buffer,
+                &self.fixed_bytes,
+                &mut sealed,
+            )
+            .map_err(|_| VaultError::Encryption.into_io())?;
+        self.writer.write_all(&sealed)?;
+        self.buffer.clear();
+        self.counter = self.counter.checked_add(1).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "too many chunks for one vault")
+        })?;
+        Ok(())
+    }
+
+    /// Seals the last chunk and hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        // The last chunk has to be short for the reader to know it's the last
+        if self.buffer.len() == self.chunk_size {
+            self.seal_chunk(false)?;
+        }
+        self.seal_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<'a, W: Write> Write for StreamSealer<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() == self.chunk_size {
+            self.seal_chunk(false)?;
+        }
+        let len = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decrypts a vault as it is read, one chunk at a time. Reads fail as soon as
+/// a chunk doesn't authenticate, and at the end if the vault was cut short,
+/// with an `InvalidData` error that converts into the matching `VaultError`.
+pub struct StreamOpener<'a, R: Read> {
+    reader: R,
+    key: &'a SecretKey,
+    header: VaultHeader,
+    fixed_bytes: Vec<u8>,
+    prefix: Vec<u8>,
+    counter: u32,
+    sealed: Vec<u8>,
+    chunk: Zeroizing<Vec<u8>>,
+    position: usize,
+    finished: bool,
+}
+
+impl<'a, R: Read> StreamOpener<'a, R> {
+    /// Reads the header and nonce prefix from `reader`. A header whose MAC
+    /// `key` doesn't check fails with `WrongKey`, like a first chunk that
+    /// doesn't open.
+    pub fn new(key: &'a SecretKey, mut reader: R) -> io::Result<StreamOpener<'a, R>> {
+        let header_bytes = VaultHeader::read_bytes(&mut reader)?;
+        StreamOpener::with_header_bytes(key, reader, header_bytes)
+    }
+
+    pub(crate) fn with_header_bytes(
+        key: &'a SecretKey,
+        mut reader: R,
+        header_bytes: Vec<u8>,
+    ) -> io::Result<StreamOpener<'a, R>> {
+        let (header, _) =
+            VaultHeader::parse(&header_bytes).map_err(|_| VaultError::Corrupted.into_io())?;
+        VaultHeader::verify(&header_bytes, key).map_err(|_| VaultError::WrongKey.into_io())?;
+        let backend = header.cipher.backend();
+        let mut prefix = vec![0u8; backend.nonce_len() - COUNTER_LEN - 1];
+        reader.read_exact(&mut prefix)?;
+
+        let chunk_size = header.chunk_size as usize;
+        Ok(StreamOpener {
+            reader,
+            key,
+            header,
+            fixed_bytes: header_bytes[..FIXED_PART_LEN].to_vec(),
+            prefix,
+            counter: 0,
+            sealed: vec![0u8; chunk_size + backend.tag_len()],
+            chunk: Zeroizing::new(vec![0u8; chunk_size]),
+            position: chunk_size,
+            finished: false,
+        })
+    }
+
+    pub fn header(&self) -> &VaultHeader {
+        &self.header
+    }
+
+    // Reads and opens the next chunk. A chunk that doesn't fill the buffer
+    // must be the last one.
+    fn open_chunk(&mut self) -> io::Result<()> {
+        let mut len = 0;
+        while len < self.sealed.len() {
+            match self.reader.read(&mut self.sealed[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let backend = self.header.cipher.backend();
+        let last = len < self.sealed.len();
+        if len < backend.tag_len() {
+            return Err(VaultError::Corrupted.into_io());
+        }
+
+        let nonce = chunk_nonce(&self.prefix, self.counter, last);
+        let plaintext_len = len - backend.tag_len();
+        backend
+            .open(
+                self.key,
+                &nonce,
+                &self.sealed[..len],
+                &self.fixed_bytes,
+                &mut self.chunk[..plaintext_len],
+            )
+            .map_err(|_| {
+                // A wrong key already fails the first chunk, a failure later on
+                // means the file was changed
+                if self.counter == 0 {
+                    VaultError::WrongKey.into_io()
+                } else {
+                    VaultError::Corrupted.into_io()
+                }
+            })?;
+        self.chunk.truncate(plaintext_len);
+        self.position = 0;
+        self.finished = last;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| VaultError::Corrupted.into_io())?;
+        Ok(())
+    }
+}
+
+impl<'a, R: Read> Read for StreamOpener<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Encrypts everything `reader` yields into a vault written to `writer`,
+/// holding one chunk in memory at a time.
+pub fn encrypt_stream<R: Read, W: Write>(
+    key: &SecretKey,
+    header: &VaultHeader,
+    reader: &mut R,
+    writer: W,
+) -> io::Result<W> {
+    let mut sealer = StreamSealer::new(key, header, writer)?;
+    copy(reader, &mut sealer, header.chunk_size as usize)?;
+    sealer.finish()
+}
+
+/// Decrypts the vault `reader` yields into `writer`, holding one chunk in
+/// memory at a time. Whatever was written before a chunk failed to open
+/// should be thrown away.
+pub fn decrypt_stream<R: Read, W: Write>(
+    key: &SecretKey,
+    reader: R,
+    writer: &mut W,
+) -> io::Result<VaultHeader> {
+    let mut opener = StreamOpener::new(key, reader)?;
+    let chunk_size = opener.header.chunk_size as usize;
+    copy(&mut opener, writer, chunk_size)?;
+    Ok(opener.header)
+}
+
+// Like `io::copy` but through a buffer that is wiped afterwards, since it
+// holds plaintext
+fn copy<R: Read, W: Write>(reader: &mut R, writer: &mut W, buffer_len: usize) -> io::Result<()> {
+    let mut buffer = Zeroizing::new(vec![0u8; buffer_len]);
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..len])?;
+    }
+}
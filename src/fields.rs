@@ -0,0 +1,195 @@
+// The custom fields in the add form. Each field is edited on its own, its name
+// and value apart, so whatever they hold is saved back as it is and a field
+// the user doesn't touch is left exactly as the entry had it.
+
+use arustylock::vault::CustomField;
+use zeroize::Zeroize;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum FieldPart {
+    #[default]
+    Name,
+    Value,
+}
+
+#[derive(Default)]
+pub struct FieldsForm {
+    fields: Vec<CustomField>,
+    selected: usize,
+    part: FieldPart,
+}
+
+impl FieldsForm {
+    pub fn new(fields: &[CustomField]) -> FieldsForm {
+        FieldsForm {
+            fields: fields.to_vec(),
+            ..FieldsForm::default()
+        }
+    }
+
+    /// The fields to save. One added but left without a name or a value is
+    /// left out.
+    pub fn fields(&self) -> Vec<CustomField> {
+        self.fields
+            .iter()
+            .filter(|field| !field.name.is_empty() || !field.value.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Adds an empty field below the others and selects its name.
+    pub fn add(&mut self) {
+        self.fields.push(CustomField::default());
+        self.selected = self.fields.len() - 1;
+        self.part = FieldPart::Name;
+    }
+
+    pub fn remove(&mut self) {
+        if self.selected < self.fields.len() {
+            self.fields.remove(self.selected);
+        }
+        self.selected = self.selected.min(self.fields.len().saturating_sub(1));
+    }
+
+    pub fn toggle_concealed(&mut self) {
+        if let Some(field) = self.fields.get_mut(self.selected) {
+            field.concealed = !field.concealed;
+        }
+    }
+
+    /// Selects the field below, or above with `step` -1. Returns false at
+    /// either end, where moving leaves the custom fields.
+    pub fn step(&mut self, step: isize) -> bool {
+        let index = self.selected as isize + step;
+        if index < 0 || index >= self.fields.len() as isize {
+            return false;
+        }
+        self.selected = index as usize;
+        self.part = FieldPart::Name;
+        true
+    }
+
+    /// Selects the first field, or the last one when coming from below.
+    pub fn enter(&mut self, from_below: bool) {
+        self.selected = if from_below {
+            self.fields.len().saturating_sub(1)
+        } else {
+            0
+        };
+        self.part = FieldPart::Name;
+    }
+
+    /// Switches between typing the name and the value of the field.
+    pub fn switch_part(&mut self) {
+        self.part = match self.part {
+            FieldPart::Name => FieldPart::Value,
+            FieldPart::Value => FieldPart::Name,
+        };
+    }
+
+    pub fn part(&self) -> FieldPart {
+        self.part
+    }
+
+    /// The name or value being typed, `None` without any field.
+    pub fn input_mut(&mut self) -> Option<&mut String> {
+        let part = self.part;
+        self.fields.get_mut(self.selected).map(|field| match part {
+            FieldPart::Name => &mut field.name,
+            FieldPart::Value => &mut field.value,
+        })
+    }
+
+    /// One line per field, with concealed values masked, and the index of
+    /// the selected one.
+    pub fn lines(&self) -> (Vec<String>, usize) {
+        let lines = self
+            .fields
+            .iter()
+            .map(|field| {
+                let value = if field.concealed {
+                    "*".repeat(field.value.chars().count())
+                } else {
+                    field.value.clone()
+                };
+                let marker = if field.concealed { " (concealed)" } else { "" };
+                format!("{}: {}{}", field.name, value, marker)
+            })
+            .collect();
+        (lines, self.selected)
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.iter_mut().for_each(Zeroize::zeroize);
+        self.fields.clear();
+        self.selected = 0;
+        self.part = FieldPart::Name;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<CustomField> {
+        vec![
+            CustomField::new("account; branch", "12=34; 56", false),
+            CustomField::new("*pin=", "0000;", true),
+            CustomField::new("note", "", false),
+        ]
+    }
+
+    #[test]
+    fn test_fields_round_trip() {
+        let fields = sample();
+        assert_eq!(FieldsForm::new(&fields).fields(), fields);
+    }
+
+    #[test]
+    fn test_edit_one_field() {
+        let mut form = FieldsForm::new(&sample());
+        assert!(form.step(1));
+        form.switch_part();
+        form.input_mut().unwrap().push_str("=1");
+        form.toggle_concealed();
+
+        let mut expected = sample();
+        expected[1].value.push_str("=1");
+        expected[1].concealed = false;
+        assert_eq!(form.fields(), expected);
+    }
+
+    #[test]
+    fn test_add_and_remove_fields() {
+        let mut form = FieldsForm::new(&sample());
+        form.add();
+        form.input_mut().unwrap().push_str("a;b=c");
+        form.add();
+        assert_eq!(form.fields().len(), 4);
+        assert_eq!(form.fields()[3], CustomField::new("a;b=c", "", false));
+
+        form.enter(false);
+        form.remove();
+        assert_eq!(form.fields()[0], sample()[1]);
+        form.enter(true);
+        form.remove();
+        form.remove();
+        form.remove();
+        form.remove();
+        assert!(form.is_empty());
+        assert!(form.input_mut().is_none());
+        assert!(!form.step(1));
+    }
+
+    #[test]
+    fn test_concealed_values_masked() {
+        let (lines, selected) = FieldsForm::new(&sample()).lines();
+        assert_eq!(selected, 0);
+        assert_eq!(lines[0], "account; branch: 12=34; 56");
+        assert_eq!(lines[1], "*pin=: ***** (concealed)");
+    }
+}
@@ -0,0 +1,83 @@
+// Hardened mode, for running arustylock on machines shared with other users.
+// The process is made non-dumpable, so it can't be core dumped or attached
+// to with ptrace, and all of its memory is locked in RAM so the decrypted
+// entries and the vault key never reach swap. Only Linux is supported.
+
+#[cfg(target_os = "linux")]
+use arustylock::encryption::kdf::DEFAULT_MEMORY_KIB;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOCK_FAILED: AtomicBool = AtomicBool::new(false);
+
+// Once everything is locked a new allocation fails when it would go over the
+// memory lock limit, and Argon2 allocates all of its memory cost at once when
+// the master password is checked. So locking is only tried with room for
+// that on top of a large store and the terminal buffers
+#[cfg(target_os = "linux")]
+const MIN_MEMLOCK: libc::rlim_t = DEFAULT_MEMORY_KIB as libc::rlim_t * 1024 + (64 << 20);
+
+/// Turns hardened mode on. Fails if core dumps couldn't be disabled. Memory
+/// that couldn't be locked is only reported by `warning`.
+#[cfg(target_os = "linux")]
+pub fn enable() -> io::Result<()> {
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    ENABLED.store(true, Ordering::SeqCst);
+    if !lock_all() {
+        LOCK_FAILED.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enable() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "hardened mode is only supported on Linux",
+    ))
+}
+
+// Locks the pages the process has now and every page it maps later, after
+// raising the memory lock limit as far as it is allowed to go
+#[cfg(target_os = "linux")]
+fn lock_all() -> bool {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        return false;
+    }
+    if limit.rlim_cur != limit.rlim_max {
+        let raised = libc::rlimit {
+            rlim_cur: limit.rlim_max,
+            rlim_max: limit.rlim_max,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &raised) } == 0 {
+            limit = raised;
+        }
+    }
+    if limit.rlim_cur != libc::RLIM_INFINITY && limit.rlim_cur < MIN_MEMLOCK {
+        return false;
+    }
+    unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) == 0 }
+}
+
+/// What to show in the status area when hardened mode couldn't do its job.
+pub fn warning() -> Option<&'static str> {
+    if ENABLED.load(Ordering::SeqCst) && LOCK_FAILED.load(Ordering::SeqCst) {
+        Some("Hardened mode: memory couldn't be locked, RLIMIT_MEMLOCK is below 128 MiB. Secrets may be written to swap")
+    } else {
+        None
+    }
+}
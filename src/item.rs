@@ -0,0 +1,383 @@
+use serde::{Deserialize, Serialize};
+use std::mem;
+use thiserror::Error;
+use zeroize::Zeroize;
+
+// What kind of thing an entry holds, with the fields of that kind. For a
+// login the entry's domain is the site, for the other kinds just its name.
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ItemError {
+    #[error("the card number isn't valid")]
+    CardNumber,
+    #[error("the expiry date should look like MM/YY")]
+    Expiry,
+    #[error("the security code should be 3 or 4 digits")]
+    SecurityCode,
+    #[error("the email address isn't valid")]
+    Email,
+    #[error("the private key isn't a PEM private key")]
+    PrivateKey,
+    #[error("the public key isn't an OpenSSH public key")]
+    PublicKey,
+    #[error("the public key doesn't belong to the private key")]
+    KeyMismatch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Item {
+    Login(Login),
+    Note,
+    Card(Card),
+    Identity(Identity),
+    SshKey(SshKey),
+}
+
+impl Item {
+    /// An empty item of each kind, in the order they are offered.
+    pub fn kinds() -> [Item; 5] {
+        [
+            Item::Login(Login::default()),
+            Item::Note,
+            Item::Card(Card::default()),
+            Item::Identity(Identity::default()),
+            Item::SshKey(SshKey::default()),
+        ]
+    }
+
+    /// Whether `other` is the same kind of item, whatever either holds.
+    pub fn same_kind(&self, other: &Item) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+
+    /// What the kind of item is called.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Item::Login(_) => "Login",
+            Item::Note => "Secure note",
+            Item::Card(_) => "Credit card",
+            Item::Identity(_) => "Identity",
+            Item::SshKey(_) => "SSH key",
+        }
+    }
+
+    /// Checks the fields of the item that have a format. Empty optional
+    /// fields are fine.
+    pub fn validate(&self) -> Result<(), ItemError> {
+        match self {
+            Item::Login(_) | Item::Note => Ok(()),
+            Item::Card(card) => card.validate(),
+            Item::Identity(identity) => identity.validate(),
+            Item::SshKey(key) => key.validate(),
+        }
+    }
+}
+
+impl Default for Item {
+    fn default() -> Item {
+        Item::Login(Login::default())
+    }
+}
+
+impl Zeroize for Item {
+    fn zeroize(&mut self) {
+        match self {
+            Item::Login(login) => login.zeroize(),
+            Item::Note => {}
+            Item::Card(card) => card.zeroize(),
+            Item::Identity(identity) => identity.zeroize(),
+            Item::SshKey(key) => key.zeroize(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Login {
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub urls: Vec<String>,
+}
+
+impl Login {
+    pub fn new<S: Into<String>>(username: S, password: S) -> Login {
+        Login {
+            username: username.into(),
+            password: password.into(),
+            urls: Vec::new(),
+        }
+    }
+}
+
+impl Zeroize for Login {
+    fn zeroize(&mut self) {
+        self.username.zeroize();
+        self.password.zeroize();
+        self.urls.zeroize();
+    }
+}
+
+impl Drop for Login {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Card {
+    pub holder: String,
+    pub number: String,
+    /// As written on the card, `MM/YY`.
+    pub expiry: String,
+    pub security_code: String,
+}
+
+impl Card {
+    fn validate(&self) -> Result<(), ItemError> {
+        if !luhn_valid(&self.number) {
+            return Err(ItemError::CardNumber);
+        }
+        if !self.expiry.is_empty() && parse_expiry(&self.expiry).is_none() {
+            return Err(ItemError::Expiry);
+        }
+        let code = &self.security_code;
+        let code_valid = (3..=4).contains(&code.len()) && code.bytes().all(|b| b.is_ascii_digit());
+        if !code.is_empty() && !code_valid {
+            return Err(ItemError::SecurityCode);
+        }
+        Ok(())
+    }
+
+    /// The expiry date as year and month, `None` if there isn't a valid one.
+    pub fn expires(&self) -> Option<(u32, u32)> {
+        parse_expiry(&self.expiry)
+    }
+}
+
+impl Zeroize for Card {
+    fn zeroize(&mut self) {
+        self.holder.zeroize();
+        self.number.zeroize();
+        self.expiry.zeroize();
+        self.security_code.zeroize();
+    }
+}
+
+impl Drop for Card {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub full_name: String,
+    pub email: String,
+    pub phone: String,
+    pub address: String,
+}
+
+impl Identity {
+    fn validate(&self) -> Result<(), ItemError> {
+        // Only catches obvious typos, the rules for addresses are too loose to
+        // be worth checking
+        let valid = match self.email.split_once('@') {
+            Some((user, domain)) => !user.is_empty() && !domain.is_empty(),
+            None => self.email.is_empty(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ItemError::Email)
+        }
+    }
+}
+
+impl Zeroize for Identity {
+    fn zeroize(&mut self) {
+        self.full_name.zeroize();
+        self.email.zeroize();
+        self.phone.zeroize();
+        self.address.zeroize();
+    }
+}
+
+impl Drop for Identity {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SshKey {
+    /// The key file in PEM form, as written by ssh-keygen.
+    pub private_key: String,
+    /// One line, as in a `.pub` file.
+    pub public_key: String,
+    /// What the private key is encrypted with, if anything.
+    pub passphrase: String,
+}
+
+impl SshKey {
+    // Keys in OpenSSH's own format carry their public key, which the one
+    // given has to match. Older PEM formats are only checked for their armor.
+    fn validate(&self) -> Result<(), ItemError> {
+        let label = pem_label(&self.private_key).ok_or(ItemError::PrivateKey)?;
+        if !label.ends_with("PRIVATE KEY") {
+            return Err(ItemError::PrivateKey);
+        }
+        if self.public_key.trim().is_empty() {
+            return Ok(());
+        }
+        let public = public_key_blob(&self.public_key).ok_or(ItemError::PublicKey)?;
+        if label == "OPENSSH PRIVATE KEY" {
+            let private = openssh_public_blob(&self.private_key).ok_or(ItemError::PrivateKey)?;
+            if private != public {
+                return Err(ItemError::KeyMismatch);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Zeroize for SshKey {
+    fn zeroize(&mut self) {
+        self.private_key.zeroize();
+        self.public_key.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+impl Drop for SshKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Whether `number` passes the Luhn check card numbers are made to pass.
+/// Spaces and dashes between the digits are ignored.
+pub fn luhn_valid(number: &str) -> bool {
+    let digits: Option<Vec<u32>> = number
+        .chars()
+        .filter(|c| *c != ' ' && *c != '-')
+        .map(|c| c.to_digit(10))
+        .collect();
+    let digits = match digits {
+        Some(digits) if (12..=19).contains(&digits.len()) => digits,
+        _ => return false,
+    };
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+// `MM/YY` or `MM/YYYY` as year and month
+fn parse_expiry(expiry: &str) -> Option<(u32, u32)> {
+    let (month, year) = expiry.trim().split_once('/')?;
+    if month.len() != 2 || !(year.len() == 2 || year.len() == 4) {
+        return None;
+    }
+    let month: u32 = month.parse().ok()?;
+    let year: u32 = year.parse().ok()?;
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    Some((if year < 100 { 2000 + year } else { year }, month))
+}
+
+// The label between `-----BEGIN ` and `-----`, if the armor is whole
+fn pem_label(pem: &str) -> Option<&str> {
+    let pem = pem.trim();
+    let label = pem.strip_prefix("-----BEGIN ")?.split("-----").next()?;
+    if pem.ends_with(&format!("-----END {}-----", label)) {
+        Some(label)
+    } else {
+        None
+    }
+}
+
+// The public key an OpenSSH private key file starts with, which is there
+// unencrypted even when the private key isn't
+fn openssh_public_blob(pem: &str) -> Option<Vec<u8>> {
+    let body: String = pem
+        .trim()
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let data = base64_decode(&body)?;
+    let mut rest = data.strip_prefix(b"openssh-key-v1\0")?;
+    // Cipher, KDF and KDF options
+    for _ in 0..3 {
+        read_string(&mut rest)?;
+    }
+    let count = read_u32(&mut rest)?;
+    if count == 0 {
+        return None;
+    }
+    Some(read_string(&mut rest)?.to_vec())
+}
+
+// The key from a `.pub` line, which names its type both outside and inside
+fn public_key_blob(line: &str) -> Option<Vec<u8>> {
+    let mut words = line.split_whitespace();
+    let kind = words.next()?;
+    let blob = base64_decode(words.next()?)?;
+    let mut rest = blob.as_slice();
+    if read_string(&mut rest)? != kind.as_bytes() {
+        return None;
+    }
+    Some(blob)
+}
+
+fn read_u32(data: &mut &[u8]) -> Option<u32> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (int, rest) = data.split_at(4);
+    *data = rest;
+    Some(u32::from_be_bytes([int[0], int[1], int[2], int[3]]))
+}
+
+fn read_string<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_u32(data)? as usize;
+    if data.len() < len {
+        return None;
+    }
+    let (string, rest) = data.split_at(len);
+    *data = rest;
+    Some(string)
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = ((buffer << 6) | value as u32) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
@@ -1 +1,7 @@
+pub mod atomic;
+pub mod backup;
 pub mod encryption;
+pub mod item;
+pub mod lock;
+pub mod stores;
+pub mod vault;
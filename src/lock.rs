@@ -0,0 +1,100 @@
+use orion::hash::{digest, Digest};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Keeps two processes from overwriting each other's changes to a store.
+// Anything that replaces a store holds an exclusive lock on `<store>.lock`
+// while it does; the store itself can't be locked since saving renames a
+// new file over it. The lock is advisory, it only keeps out other arustylock
+// processes.
+//
+// A session that keeps a store open remembers a fingerprint of the file it
+// read. Before writing, with the lock held, it checks the file still has that
+// fingerprint, so changes made in the meantime by someone else are noticed
+// instead of overwritten.
+
+fn lock_path(store_path: &Path) -> PathBuf {
+    let mut lock = store_path.as_os_str().to_owned();
+    lock.push(".lock");
+    PathBuf::from(lock)
+}
+
+/// An exclusive lock on a store, released when dropped.
+pub struct StoreLock {
+    _file: File,
+}
+
+impl StoreLock {
+    /// Waits until no other process holds the lock on `store_path`, then
+    /// takes it.
+    pub fn acquire(store_path: &Path) -> io::Result<StoreLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(store_path))?;
+        file.lock()?;
+        Ok(StoreLock { _file: file })
+    }
+}
+
+/// What a store's file looked like when it was read or written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+    digest: Digest,
+}
+
+impl Fingerprint {
+    /// Fingerprints the file at `path`, `None` if there is no file.
+    pub fn of(path: &Path) -> io::Result<Option<Fingerprint>> {
+        // The time is taken first, so if the file is replaced in between the
+        // fingerprint is of the newer contents with an older time, which
+        // `still_matches` only takes for a change
+        let modified = match fs::metadata(path) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let contents = fs::read(path)?;
+        Ok(Some(Fingerprint::of_contents(&contents, modified)))
+    }
+
+    /// Fingerprints `contents`, as read from a file last modified at
+    /// `modified`.
+    pub fn of_contents(contents: &[u8], modified: Option<SystemTime>) -> Fingerprint {
+        Fingerprint {
+            len: contents.len() as u64,
+            modified,
+            digest: digest(contents).expect("BLAKE2b-256 without a key can't fail"),
+        }
+    }
+
+    /// Whether `contents` are the ones fingerprinted.
+    pub fn matches(&self, contents: &[u8]) -> bool {
+        self.len == contents.len() as u64
+            && digest(contents).is_ok_and(|digest| digest == self.digest)
+    }
+
+    /// Whether the file at `path` still has this fingerprint. Only the size
+    /// and modification time are looked at unless one of them changed, so
+    /// this is cheap enough to poll.
+    pub fn still_matches(&self, path: &Path) -> io::Result<bool> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if metadata.len() != self.len {
+            return Ok(false);
+        }
+        if self.modified.is_some() && metadata.modified().ok() == self.modified {
+            return Ok(true);
+        }
+        Ok(self.matches(&fs::read(path)?))
+    }
+}
@@ -1,14 +1,29 @@
-mod encryption;
-
+mod cli;
+mod fields;
+mod hardening;
+mod unlock;
+
+use arustylock::atomic::replace_file_with;
+use arustylock::encryption::encryption::write_encrypted;
+use arustylock::encryption::error::VaultError;
+use arustylock::encryption::header::VaultHeader;
+use arustylock::encryption::legacy::is_legacy;
+use arustylock::encryption::recovery::{RecoveryKey, RecoveryPhrase, RecoveryShare};
+use arustylock::encryption::shamir::ShamirError;
+use arustylock::item::{Card, Identity, Item, Login, SshKey};
+use arustylock::stores;
+use arustylock::vault::{EntryId, StoreError, Vault};
+use chrono::{DateTime, Local, Utc};
+use cli::Subcommand;
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, KeyEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use encryption::encryption::{decrypt_data, encrypt_data, reset_file_cursor};
-use orion::{aead, aead::SecretKey};
-use serde::{Deserialize, Serialize};
-use std::io::prelude::*;
+use fields::{FieldPart, FieldsForm};
+use orion::{aead::SecretKey, errors::UnknownCryptoError};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::path::Path;
 use std::process::Command;
 use std::str;
@@ -16,19 +31,20 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 use std::{fs, io::Stdout};
-use std::{fs::OpenOptions, str::from_utf8};
 use std::{io, process::exit};
 use thiserror::Error;
 use tui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    text::{Span, Spans},
+    text::{Span, Spans, Text},
     widgets::{
         Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs,
+        Wrap,
     },
     Terminal,
 };
+use zeroize::{Zeroize, Zeroizing};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -36,6 +52,30 @@ pub enum Error {
     ReadDBError(#[from] io::Error),
     #[error("error parsing the DB file: {0}")]
     ParseDBError(#[from] serde_json::Error),
+    #[error("the master password is wrong")]
+    WrongPassword,
+    #[error("the master password or keyfile is wrong")]
+    WrongPasswordOrKeyfile,
+    #[error("this password store needs a keyfile, pass it with --keyfile PATH")]
+    KeyfileRequired,
+    #[error("this password store doesn't use a keyfile, run without --keyfile")]
+    KeyfileNotUsed,
+    #[error("there is no key slot {0}")]
+    NoSuchKeySlot(usize),
+    #[error("the last key slot can't be removed")]
+    LastKeySlot,
+    #[error("there is no backup {0}")]
+    NoSuchBackup(usize),
+    #[error("this password store needs upgrading, start arustylock with it first")]
+    NeedsUpgrade,
+    #[error("{0}")]
+    ShareError(#[from] ShamirError),
+    #[error("{0}")]
+    VaultError(#[from] VaultError),
+    #[error("{0}")]
+    StoreError(#[from] StoreError),
+    #[error("error encrypting the DB file")]
+    CryptoError(#[from] UnknownCryptoError),
 }
 
 enum Event<I> {
@@ -43,102 +83,390 @@ enum Event<I> {
     Tick,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Password {
-    domain: String,
-    username: String,
-    password: String,
-}
-
 #[derive(Copy, Clone, Debug)]
 enum MenuItem {
     Home,
     Passwords,
     AddPassword,
+    ChangePassword,
+    Shares,
+    Vaults,
 }
 
-enum InputMode {
-    DomainEditing,
-    DomainNormal,
-    UsernameEditing,
-    UsernameNormal,
-    PasswordEditing,
-    PasswordNormal,
+// The fields the form has for an entry of the same kind as `kind`, top to
+// bottom. The folder is shown next to the type.
+fn form_fields(kind: &Item) -> &'static [EntryField] {
+    use EntryField::*;
+    match kind {
+        Item::Login(_) => &[
+            Folder, Domain, Username, Password, Urls, Tags, Fields, Notes,
+        ],
+        Item::Note => &[Folder, Domain, Tags, Fields, Notes],
+        Item::Card(_) => &[
+            Folder,
+            Domain,
+            Holder,
+            Number,
+            Expiry,
+            SecurityCode,
+            Tags,
+            Fields,
+            Notes,
+        ],
+        Item::Identity(_) => &[
+            Folder, Domain, FullName, Email, Phone, Address, Tags, Fields, Notes,
+        ],
+        Item::SshKey(_) => &[
+            Folder, Domain, PrivateKey, PublicKey, Passphrase, Tags, Fields, Notes,
+        ],
+    }
 }
 
-impl Default for InputMode {
-    fn default() -> Self {
-        InputMode::DomainNormal
+// The kind after `kind` in `Item::kinds`, back to the first after the last
+fn next_kind(kind: &Item) -> Item {
+    let kinds = Item::kinds();
+    let index = kinds
+        .iter()
+        .position(|other| other.same_kind(kind))
+        .unwrap_or(0);
+    kinds[(index + 1) % kinds.len()].clone()
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum EntryField {
+    Folder,
+    #[default]
+    Domain,
+    Username,
+    Password,
+    Urls,
+    Holder,
+    Number,
+    Expiry,
+    SecurityCode,
+    FullName,
+    Email,
+    Phone,
+    Address,
+    PrivateKey,
+    PublicKey,
+    Passphrase,
+    Tags,
+    Fields,
+    Notes,
+}
+
+impl EntryField {
+    const COUNT: usize = 19;
+
+    fn title(self, kind: &Item) -> &'static str {
+        match self {
+            EntryField::Folder => "Folder, like work/aws",
+            EntryField::Domain if matches!(kind, Item::Login(_)) => "Domain",
+            EntryField::Domain => "Name",
+            EntryField::Username => "Username",
+            EntryField::Password => "Password",
+            EntryField::Urls => "URLs, separated by spaces",
+            EntryField::Holder => "Cardholder",
+            EntryField::Number => "Card number",
+            EntryField::Expiry => "Expiry date, MM/YY",
+            EntryField::SecurityCode => "Security code",
+            EntryField::FullName => "Full name",
+            EntryField::Email => "Email",
+            EntryField::Phone => "Phone",
+            EntryField::Address => "Address",
+            EntryField::PrivateKey => "Private key file",
+            EntryField::PublicKey => "Public key, read from the .pub file next to the key if empty",
+            EntryField::Passphrase => "Passphrase of the key",
+            EntryField::Tags => "Tags, separated by commas",
+            EntryField::Fields => {
+                "Custom fields, 'n' to add one, 'x' to remove it and '*' to conceal its value"
+            }
+            EntryField::Notes => "Notes",
+        }
+    }
+
+    // The field below, or above with `step` -1, among those `kind` has,
+    // staying put at either end
+    fn step(self, kind: &Item, step: isize) -> EntryField {
+        let fields = form_fields(kind);
+        let index = fields.iter().position(|&field| field == self).unwrap_or(0);
+        let last = fields.len() as isize - 1;
+        fields[(index as isize + step).clamp(0, last) as usize]
     }
 }
+
 // struct for managing state in adding new credentials
 #[derive(Default)]
 struct InputState {
-    input_domain: String,
-    input_username: String,
-    input_password: String,
-    input_mode: InputMode,
+    // Indexed by `EntryField`
+    inputs: [String; EntryField::COUNT],
+    // An empty item of the kind the entry is, from `Item::kinds`
+    kind: Item,
+    field: EntryField,
+    editing: bool,
+    // The entry being edited, `None` when adding a new one
+    entry: Option<EntryId>,
+    // Edited apart from `inputs`, one field at a time
+    fields: FieldsForm,
+    message: String,
 }
 
-// struct for managing overall app state
-#[derive(Default)]
+impl InputState {
+    fn input(&self, field: EntryField) -> &str {
+        &self.inputs[field as usize]
+    }
+
+    // What typing goes into, `None` on the custom fields until one is added
+    fn input_mut(&mut self) -> Option<&mut String> {
+        match self.field {
+            EntryField::Fields => self.fields.input_mut(),
+            field => Some(&mut self.inputs[field as usize]),
+        }
+    }
+
+    // Moves to the field below, or above with `step` -1, going through the
+    // custom fields one at a time
+    fn step(&mut self, step: isize) {
+        if self.field == EntryField::Fields && self.fields.step(step) {
+            return;
+        }
+        self.field = self.field.step(&self.kind, step);
+        if self.field == EntryField::Fields {
+            self.fields.enter(step < 0);
+        }
+    }
+}
+
+impl Drop for InputState {
+    fn drop(&mut self) {
+        clear_input(self);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RekeyField {
+    Current,
+    New,
+    Confirm,
+}
+
+// struct for managing state in changing the master password
+struct RekeyState {
+    current: String,
+    new: String,
+    confirm: String,
+    field: RekeyField,
+    editing: bool,
+    message: String,
+}
+
+impl Default for RekeyState {
+    fn default() -> Self {
+        RekeyState {
+            current: String::new(),
+            new: String::new(),
+            confirm: String::new(),
+            field: RekeyField::Current,
+            editing: false,
+            message: String::from("Press 'i' to edit a field and Enter to change the password"),
+        }
+    }
+}
+
+impl Drop for RekeyState {
+    fn drop(&mut self) {
+        self.current.zeroize();
+        self.new.zeroize();
+        self.confirm.zeroize();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SharesField {
+    Threshold,
+    Count,
+    Share,
+}
+
+// struct for managing state in splitting the recovery key into shares and
+// putting it back together
+struct SharesState {
+    threshold: String,
+    count: String,
+    share: String,
+    entered: Vec<RecoveryShare>,
+    field: SharesField,
+    editing: bool,
+    message: String,
+    output: Vec<String>,
+}
+
+impl Default for SharesState {
+    fn default() -> Self {
+        SharesState {
+            threshold: String::new(),
+            count: String::new(),
+            share: String::new(),
+            entered: Vec::new(),
+            field: SharesField::Threshold,
+            editing: false,
+            message: String::from(
+                "Fill in the threshold and share count and press Enter to split a new recovery key, or enter shares one at a time to put the recovery key back together",
+            ),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Drop for SharesState {
+    fn drop(&mut self) {
+        self.share.zeroize();
+        self.output.zeroize();
+    }
+}
+
+// struct for managing state in switching to another vault
+struct VaultsState {
+    names: Vec<String>,
+    list_state: ListState,
+    password: String,
+    editing: bool,
+    message: String,
+}
+
+impl Default for VaultsState {
+    fn default() -> Self {
+        VaultsState {
+            names: Vec::new(),
+            list_state: ListState::default(),
+            password: String::new(),
+            editing: false,
+            message: String::from(
+                "Pick a vault with j and k, press 'i' to type its master password and Enter to open it",
+            ),
+        }
+    }
+}
+
+impl VaultsState {
+    // Looks for vaults again, selecting the open one
+    fn refresh(&mut self, app: &AppState) {
+        self.names = stores::list(Path::new(&app.config_dir)).unwrap_or_default();
+        let open = self.names.iter().position(|name| *name == app.vault_name);
+        self.list_state.select(open.or(Some(0)));
+    }
+}
+
+impl Drop for VaultsState {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+// struct for managing overall app state. The decrypted store is kept in
+// `vault` for the whole session and only written back after a change.
 struct AppState {
-    config_path: String,
+    config_dir: String,
+    vault_name: String,
     store_path: String,
-    secret_key: aead::SecretKey,
+    vault: Vault,
+    keyfile: Option<Zeroizing<Vec<u8>>>,
+    // Set while unsaved changes wait on a store someone else wrote
+    conflict: Option<String>,
+    // Why the last save failed, the changes stay unsaved until one works
+    save_error: Option<String>,
+    // Why the last key press failed, shown until the next one
+    action_error: Option<String>,
+    // Whether the selected entry's concealed fields are shown
+    reveal: bool,
+    // The folders open in the tree, all others are collapsed
+    expanded: HashSet<String>,
+    // Set while the folder to move an entry to is typed in
+    moving: Option<MoveState>,
 }
 
+struct MoveState {
+    entry: EntryId,
+    folder: String,
+}
+
+// A line of the tree in the passwords tab
+enum TreeRow {
+    Folder { path: String, depth: usize },
+    Entry { index: usize, depth: usize },
+}
+
+const CONFLICT_MESSAGE: &str = "The store was changed by another process: press r to reload it and drop your changes, m to merge them in or q to quit";
+
+// Shown by the actions that read the store again, which would drop changes
+// the last save didn't write
+const UNSAVED_MESSAGE: &str =
+    "Some changes haven't been saved yet, see the bottom of the screen and try again once they are";
+
 impl From<MenuItem> for usize {
     fn from(input: MenuItem) -> usize {
         match input {
             MenuItem::Home => 0,
             MenuItem::Passwords => 1,
             MenuItem::AddPassword => 2,
+            MenuItem::ChangePassword => 4,
+            MenuItem::Shares => 5,
+            MenuItem::Vaults => 6,
         }
     }
 }
 
-fn create_windows_config(store_path: &String, config_dir: &String, secret_key: &SecretKey) {
-
-    fs::create_dir_all(&config_dir).unwrap();
-         let mut store = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&store_path)
-            .unwrap();
-
-    store
-        .write(b"[{\"domain\": \"\", \"username\": \"\", \"password\": \"\" }]")
-        .unwrap();
-
-    encrypt_data(&mut store, &secret_key);
+const EMPTY_STORE: &[u8] = b"[{\"domain\": \"\", \"username\": \"\", \"password\": \"\" }]";
 
+fn create_windows_config(store_path: &str, secret_key: &SecretKey, header: &VaultHeader) {
+    fs::create_dir_all(store_dir(store_path)).unwrap();
+    replace_file_with(Path::new(store_path), |store| {
+        write_encrypted(store, secret_key, header, EMPTY_STORE)
+    })
+    .expect("Couldn't encrypt password store");
 }
 
-fn create_unix_config(store_path: &String, config_dir: &String, secret_key: &SecretKey) {
+fn create_unix_config(store_path: &str, secret_key: &SecretKey, header: &VaultHeader) {
     Command::new("mkdir")
-            .arg(config_dir)
-            .output()
-            .expect("Error making .arustylock directory");
-        let mut store = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&store_path)
-            .unwrap();
+        .arg("-p")
+        .arg(store_dir(store_path))
+        .output()
+        .expect("Error making .arustylock directory");
+    replace_file_with(Path::new(store_path), |store| {
+        write_encrypted(store, secret_key, header, EMPTY_STORE)
+    })
+    .expect("Couldn't encrypt password store");
+}
 
-        store
-            .write(b"[{\"domain\": \"\", \"username\": \"\", \"password\": \"\" }]")
-            .unwrap();
+// The config directory, or the directory of named vaults in it
+fn store_dir(store_path: &str) -> &Path {
+    Path::new(store_path)
+        .parent()
+        .expect("The store path has a directory")
+}
 
-        encrypt_data(&mut store, &secret_key);
+fn store_is_legacy(store_path: &str) -> bool {
+    let data = fs::read(store_path).expect("Couldn't read the password store");
+    is_legacy(&data)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode().expect("Can't run in raw mode");
-
-    let mut app = AppState::default();
+    let args = cli::parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("error: {}\n\n{}", e, cli::USAGE);
+        exit(2);
+    });
+    if let Subcommand::Help = args.subcommand {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    // Before anything secret is read, so nothing can be dumped
+    if args.hardened {
+        if let Err(e) = hardening::enable() {
+            eprintln!("Couldn't start in hardened mode: {}", e);
+            exit(1);
+        }
+    }
 
     // Adds in a newline char here
     // so we want to truncate it
@@ -148,41 +476,126 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .stdout;
     let len = user.len();
     user.truncate(len - 1);
-    let config_dir: String;
-
-    if cfg!(windows) {
-
-        config_dir = format!(
-           "C:\\Users\\{}\\AppData\\Roaming\\arustylock",
+    let config_dir = if cfg!(windows) {
+        format!(
+            "C:\\Users\\{}\\AppData\\Roaming\\arustylock",
             String::from_utf8(user).expect("Error reading stdout to string")
-        );
-
+        )
     } else {
-        config_dir = format!(
+        format!(
             "/home/{}/.config/arustylock",
             String::from_utf8(user).expect("Error reading stdout to string")
-        );
-    }
-
-
-    let store_path = format!("{}/data", config_dir);
+        )
+    };
 
-    // Can't use the default since it randomly generates a key each time
-    // Might need to entirely redo how we encrypt if we actually want security lol
-    // Perhaps another day...
-    let secret_key = SecretKey::from_slice("qaz123WSX$%^edcplm098IJN765uhbZQ".as_bytes()).unwrap();
+    let store_path = stores::path(Path::new(&config_dir), &args.vault)
+        .to_string_lossy()
+        .into_owned();
+    let keyfile_contents = args.keyfile.as_deref().map(unlock::read_keyfile);
+    let keyfile = keyfile_contents.as_deref().map(Vec::as_slice);
+
+    // Backups can still be restored when the store is gone or unreadable
+    if !matches!(
+        args.subcommand,
+        Subcommand::Tui | Subcommand::Vaults | Subcommand::Backups | Subcommand::RestoreBackup(_)
+    ) {
+        if !Path::new(&store_path).exists() {
+            eprintln!("There is no password store at {}", store_path);
+            exit(1);
+        }
+        if store_is_legacy(&store_path) {
+            eprintln!(
+                "The password store needs upgrading, start arustylock without a command first"
+            );
+            exit(1);
+        }
+    }
+    match &args.subcommand {
+        Subcommand::ChangePassword => {
+            unlock::run_change_password(&store_path, keyfile, args.backups);
+            return Ok(());
+        }
+        Subcommand::Recover => {
+            unlock::run_recover(&store_path, keyfile, args.backups);
+            return Ok(());
+        }
+        Subcommand::KeySlots => {
+            unlock::run_list_key_slots(&store_path);
+            return Ok(());
+        }
+        Subcommand::AddKeySlot(new_slot) => {
+            unlock::run_add_key_slot(&store_path, keyfile, new_slot, args.backups);
+            return Ok(());
+        }
+        Subcommand::NewRecoveryKey => {
+            unlock::run_new_recovery_key(&store_path, keyfile, args.backups);
+            return Ok(());
+        }
+        Subcommand::SplitRecoveryKey { threshold, count } => {
+            unlock::run_split_recovery_key(&store_path, keyfile, *threshold, *count, args.backups);
+            return Ok(());
+        }
+        Subcommand::RemoveKeySlot(index) => {
+            unlock::run_remove_key_slot(&store_path, keyfile, *index, args.backups);
+            return Ok(());
+        }
+        Subcommand::List(folder) => {
+            unlock::run_list_entries(&store_path, keyfile, folder.as_deref());
+            return Ok(());
+        }
+        Subcommand::Vaults => {
+            unlock::run_list_vaults(&config_dir);
+            return Ok(());
+        }
+        Subcommand::Backups => {
+            unlock::run_list_backups(&store_path);
+            return Ok(());
+        }
+        Subcommand::RestoreBackup(index) => {
+            unlock::run_restore_backup(&store_path, keyfile, *index, args.backups);
+            return Ok(());
+        }
+        Subcommand::Tui | Subcommand::Help => {}
+    }
 
-    if !Path::new(config_dir.as_str()).exists() {
+    // A vault that doesn't exist yet is made, the default one on first run
+    let (secret_key, _) = if !Path::new(&store_path).exists() {
+        let password = unlock::choose_master_password();
+        let (secret_key, mut header) = unlock::new_store_key(&password, keyfile, args.cipher);
+        let recovery_key = unlock::add_recovery_key(&secret_key, &mut header);
         if cfg!(windows) {
-            create_windows_config(&store_path, &config_dir, &secret_key);
+            create_windows_config(&store_path, &secret_key, &header);
         } else {
-            create_unix_config(&store_path, &config_dir, &secret_key);
+            create_unix_config(&store_path, &secret_key, &header);
         }
-    }
+        unlock::show_recovery_key(&recovery_key);
+        (secret_key, header)
+    } else if store_is_legacy(&store_path) {
+        unlock::migrate_legacy_store(&store_path, keyfile, args.cipher)
+    } else {
+        unlock::unlock_store(&store_path, keyfile)
+    };
 
-    app.config_path = config_dir;
-    app.store_path = store_path;
-    app.secret_key = secret_key;
+    let mut vault = open_vault(&store_path, &secret_key)?;
+    vault.set_backups(args.backups);
+    vault.set_history_depth(args.history);
+    // The vault keeps its own copy of the key
+    drop(secret_key);
+    let mut app = AppState {
+        config_dir,
+        vault_name: args.vault.clone(),
+        store_path,
+        vault,
+        keyfile: keyfile_contents,
+        conflict: None,
+        save_error: None,
+        action_error: None,
+        reveal: false,
+        expanded: HashSet::new(),
+        moving: None,
+    };
+
+    enable_raw_mode().expect("Can't run in raw mode");
     let (tx, rx) = mpsc::channel();
     let tick_rate = Duration::from_millis(200);
     let mut stdout = io::stdout();
@@ -193,10 +606,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let menu_titles = vec!["Home", "Passwords", "Add", "Delete", "Quit"];
+    let menu_titles = [
+        "Home",
+        "Passwords",
+        "Add",
+        "Delete",
+        "Change password",
+        "Shares",
+        "Open vault",
+        "Quit",
+    ];
     let mut active_menu_item = MenuItem::Home;
     let mut password_list_state = ListState::default();
     let mut add_password_state = InputState::default();
+    let mut rekey_state = RekeyState::default();
+    let mut shares_state = SharesState::default();
+    let mut vaults_state = VaultsState::default();
     password_list_state.select(Some(0));
     thread::spawn(move || {
         let mut last_tick = Instant::now();
@@ -211,10 +636,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            if last_tick.elapsed() >= tick_rate {
-                if let Ok(_) = tx.send(Event::Tick) {
-                    last_tick = Instant::now();
-                }
+            if last_tick.elapsed() >= tick_rate && tx.send(Event::Tick).is_ok() {
+                last_tick = Instant::now();
             }
         }
     });
@@ -235,16 +658,58 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 )
                 .split(size);
 
-            let copyright = Paragraph::new("A Rusty Lock - all rights reserved")
-                .style(Style::default().fg(Color::LightCyan))
-                .alignment(Alignment::Center)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .style(Style::default().fg(Color::White))
-                        .title("Copyright")
-                        .border_type(BorderType::Plain),
-                );
+            let (status, status_color) = match app.save_error.as_ref().or(app.action_error.as_ref())
+            {
+                Some(error) => (Some(error.as_str()), Color::Red),
+                None => (hardening::warning(), Color::Yellow),
+            };
+            let copyright = match (&app.conflict, status) {
+                (None, _) if app.moving.is_some() => {
+                    let folder = app
+                        .moving
+                        .as_ref()
+                        .map_or("", |moving| moving.folder.as_str());
+                    Paragraph::new(folder)
+                        .style(Style::default().fg(Color::Green))
+                        .block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .style(Style::default().fg(Color::White))
+                                .title("Move to folder, Enter to move or Esc to cancel")
+                                .border_type(BorderType::Plain),
+                        )
+                }
+                (Some(conflict), _) => Paragraph::new(conflict.as_str())
+                    .style(Style::default().fg(Color::Red))
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .style(Style::default().fg(Color::White))
+                            .title("Conflict")
+                            .border_type(BorderType::Plain),
+                    ),
+                (None, Some(status)) => Paragraph::new(status)
+                    .style(Style::default().fg(status_color))
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .style(Style::default().fg(Color::White))
+                            .title("Status")
+                            .border_type(BorderType::Plain),
+                    ),
+                (None, None) => Paragraph::new("A Rusty Lock - all rights reserved")
+                    .style(Style::default().fg(Color::LightCyan))
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .style(Style::default().fg(Color::White))
+                            .title("Copyright")
+                            .border_type(BorderType::Plain),
+                    ),
+            };
 
             let menu = menu_titles
                 .iter()
@@ -279,7 +744,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             [Constraint::Percentage(20), Constraint::Percentage(80)].as_ref(),
                         )
                         .split(chunks[1]);
-                    let (left, right) = render_passwords(&password_list_state, &mut app);
+                    let (left, right) = render_passwords(&password_list_state, &app);
                     rect.render_stateful_widget(
                         left,
                         passwords_chunks[0],
@@ -288,49 +753,366 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     rect.render_widget(right, passwords_chunks[1]);
                 }
                 MenuItem::AddPassword => {
-                    let add_layout = Layout::default()
+                    // The type next to the folder, then the kind's other
+                    // fields and the status below
+                    // with a line for each custom field
+                    let fields = add_password_state.fields.lines().0.len();
+                    let mut constraints: Vec<_> = form_fields(&add_password_state.kind)
+                        .iter()
+                        .map(|&field| match field {
+                            EntryField::Fields => Constraint::Length(fields.max(1) as u16 + 2),
+                            _ => Constraint::Length(3),
+                        })
+                        .collect();
+                    constraints.push(Constraint::Min(0));
+                    let mut add_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints(constraints)
+                        .split(chunks[1]);
+                    let top = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Percentage(30), Constraint::Percentage(70)].as_ref(),
+                        )
+                        .split(add_layout.remove(0));
+                    let inputs = render_create_password(&add_password_state);
+                    for (input, area) in inputs.into_iter().zip(top.into_iter().chain(add_layout)) {
+                        rect.render_widget(input, area);
+                    }
+                }
+                MenuItem::ChangePassword => {
+                    let rekey_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints(
+                            [
+                                Constraint::Length(3),
+                                Constraint::Length(3),
+                                Constraint::Length(3),
+                                Constraint::Min(3),
+                            ]
+                            .as_ref(),
+                        )
+                        .split(chunks[1]);
+                    let (current, new, confirm, message) = render_change_password(&rekey_state);
+                    rect.render_widget(current, rekey_layout[0]);
+                    rect.render_widget(new, rekey_layout[1]);
+                    rect.render_widget(confirm, rekey_layout[2]);
+                    rect.render_widget(message, rekey_layout[3]);
+                }
+                MenuItem::Shares => {
+                    let shares_layout = Layout::default()
                         .direction(Direction::Vertical)
                         .constraints(
                             [
-                                Constraint::Percentage(33),
-                                Constraint::Percentage(33),
-                                Constraint::Percentage(33),
+                                Constraint::Length(3),
+                                Constraint::Length(3),
+                                Constraint::Length(3),
+                                Constraint::Min(3),
                             ]
                             .as_ref(),
                         )
                         .split(chunks[1]);
-                    let (top, center, bottom) = render_create_password(&add_password_state);
-                    rect.render_widget(top, add_layout[0]);
-                    rect.render_widget(center, add_layout[1]);
-                    rect.render_widget(bottom, add_layout[2]);
+                    let (threshold, count, share, output) = render_shares(&shares_state);
+                    rect.render_widget(threshold, shares_layout[0]);
+                    rect.render_widget(count, shares_layout[1]);
+                    rect.render_widget(share, shares_layout[2]);
+                    rect.render_widget(output, shares_layout[3]);
+                }
+                MenuItem::Vaults => {
+                    let vaults_chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Percentage(20), Constraint::Percentage(80)].as_ref(),
+                        )
+                        .split(chunks[1]);
+                    let vaults_layout = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Length(3), Constraint::Min(3)].as_ref())
+                        .split(vaults_chunks[1]);
+                    let (list, password, message) = render_vaults(&vaults_state, &app);
+                    rect.render_stateful_widget(
+                        list,
+                        vaults_chunks[0],
+                        &mut vaults_state.list_state,
+                    );
+                    rect.render_widget(password, vaults_layout[0]);
+                    rect.render_widget(message, vaults_layout[1]);
                 }
             }
             rect.render_widget(copyright, chunks[2]);
         })?;
         let received = rx.recv().unwrap();
-        match active_menu_item {
-            MenuItem::Home => {
-                handle_home_keyevent(&received, &mut active_menu_item, &mut terminal, &mut app);
+        if let Event::Input(_) = received {
+            app.action_error = None;
+        }
+        let previous_menu_item = active_menu_item;
+        if app.conflict.is_some() {
+            handle_conflict_keyevent(&received, &mut password_list_state, &mut app, &mut terminal);
+        } else if app.moving.is_some() {
+            handle_move_keyevent(&received, &mut password_list_state, &mut app);
+        } else {
+            match active_menu_item {
+                MenuItem::Home => {
+                    handle_home_keyevent(&received, &mut active_menu_item, &mut terminal);
+                }
+                MenuItem::Passwords => {
+                    handle_passwords_keyevent(
+                        &received,
+                        &mut active_menu_item,
+                        &mut password_list_state,
+                        &mut add_password_state,
+                        &mut app,
+                        &mut terminal,
+                    );
+                }
+                MenuItem::AddPassword => {
+                    handle_add_keyevent(
+                        &received,
+                        &mut active_menu_item,
+                        &mut add_password_state,
+                        &mut app,
+                        &mut terminal,
+                    );
+                }
+                MenuItem::ChangePassword => {
+                    handle_change_password_keyevent(
+                        &received,
+                        &mut active_menu_item,
+                        &mut rekey_state,
+                        &mut app,
+                        &mut terminal,
+                    );
+                }
+                MenuItem::Shares => {
+                    handle_shares_keyevent(
+                        &received,
+                        &mut active_menu_item,
+                        &mut shares_state,
+                        &mut app,
+                        &mut terminal,
+                    );
+                }
+                MenuItem::Vaults => {
+                    handle_vaults_keyevent(
+                        &received,
+                        &mut active_menu_item,
+                        &mut vaults_state,
+                        &mut password_list_state,
+                        &mut add_password_state,
+                        &mut app,
+                        &mut terminal,
+                    );
+                }
+            }
+        }
+        if matches!(active_menu_item, MenuItem::Vaults)
+            && !matches!(previous_menu_item, MenuItem::Vaults)
+        {
+            vaults_state.refresh(&app);
+        }
+        // Someone else saved. With nothing unsaved here their changes are
+        // simply read in, otherwise the user is asked what to do on saving
+        if let Event::Tick = received {
+            if !app.vault.is_dirty() && app.vault.changed_on_disk().unwrap_or(false) {
+                reload_vault(&mut password_list_state, &mut app);
+            }
+        }
+        // The handlers only change the vault in memory, it is written out
+        // once after any event that changed it. After a failed save it is
+        // tried again on the next key press rather than every tick, since
+        // each try takes a backup
+        let retry = app.save_error.is_none() || matches!(received, Event::Input(_));
+        if app.vault.is_dirty() && app.conflict.is_none() && retry {
+            match save_vault(&mut app.vault) {
+                Ok(()) => {}
+                Err(Error::StoreError(StoreError::Changed)) => {
+                    app.conflict = Some(String::from(CONFLICT_MESSAGE))
+                }
+                Err(e) => {
+                    app.save_error = Some(format!(
+                        "Couldn't save the password store: {}. Your changes are kept, press any key to try again",
+                        e
+                    ))
+                }
             }
-            MenuItem::Passwords => {
-                handle_passwords_keyevent(
-                    &received,
-                    &mut active_menu_item,
-                    &mut password_list_state,
-                    &mut app,
-                    &mut terminal,
-                );
+        }
+        if !app.vault.is_dirty() || app.conflict.is_some() {
+            app.save_error = None;
+        }
+    }
+}
+
+fn handle_conflict_keyevent(
+    key_event: &Event<KeyEvent>,
+    password_list_state: &mut ListState,
+    app: &mut AppState,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) {
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    match event.code {
+        KeyCode::Char('r') => reload_vault(password_list_state, app),
+        KeyCode::Char('m') => {
+            let selected = selected_entry(password_list_state, app);
+            match app.vault.merge() {
+                Ok(()) => {
+                    app.conflict = None;
+                    select_entry(password_list_state, app, selected);
+                }
+                Err(e) => {
+                    app.conflict = Some(format!(
+                        "Couldn't merge with the store: {}. Press r to reload it, m to try again or q to quit",
+                        e
+                    ))
+                }
             }
-            MenuItem::AddPassword => {
-                handle_add_keyevent(
-                    &received,
-                    &mut active_menu_item,
-                    &mut add_password_state,
-                    &mut app,
-                    &mut terminal,
-                );
+        }
+        KeyCode::Char('q') => {
+            disable_raw_mode().expect("Raw mode was not disabled");
+
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )
+            .expect("Leaving alt screen failed");
+            terminal.show_cursor().expect("Unable to show cursor");
+            exit(0);
+        }
+        _ => {}
+    }
+}
+
+// The tree of folders and entries as shown, leaving out what is in collapsed
+// folders. Within a folder its subfolders come first, then its entries in
+// the order they were added.
+fn tree_rows(app: &AppState) -> Vec<TreeRow> {
+    let folders = app.vault.folders();
+    let mut rows = Vec::new();
+    push_folder_rows(&mut rows, app, &folders, "", 0);
+    rows
+}
+
+fn push_folder_rows(
+    rows: &mut Vec<TreeRow>,
+    app: &AppState,
+    folders: &[String],
+    folder: &str,
+    depth: usize,
+) {
+    for path in folders {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        if parent != folder {
+            continue;
+        }
+        rows.push(TreeRow::Folder {
+            path: path.clone(),
+            depth,
+        });
+        if app.expanded.contains(path) {
+            push_folder_rows(rows, app, folders, path, depth + 1);
+        }
+    }
+    for (index, entry) in app.vault.list().iter().enumerate() {
+        if entry.folder == folder {
+            rows.push(TreeRow::Entry { index, depth });
+        }
+    }
+}
+
+// The ID of the entry selected in the tree, `None` if it's a folder
+fn selected_entry(password_list_state: &ListState, app: &AppState) -> Option<EntryId> {
+    match password_list_state
+        .selected()
+        .and_then(|index| tree_rows(app).into_iter().nth(index))
+    {
+        Some(TreeRow::Entry { index, .. }) => Some(app.vault.list()[index].id.clone()),
+        _ => None,
+    }
+}
+
+// The folder selected in the tree, or the one the selected entry is in
+fn selected_folder(password_list_state: &ListState, app: &AppState) -> String {
+    match password_list_state
+        .selected()
+        .and_then(|index| tree_rows(app).into_iter().nth(index))
+    {
+        Some(TreeRow::Folder { path, .. }) => path,
+        Some(TreeRow::Entry { index, .. }) => app.vault.list()[index].folder.clone(),
+        None => String::new(),
+    }
+}
+
+// Selects the entry with ID `id` after the tree changed under it, opening
+// the folders it's in, or the row at the same place if it is gone
+fn select_entry(password_list_state: &mut ListState, app: &mut AppState, id: Option<EntryId>) {
+    let entry = id.and_then(|id| app.vault.position(&id));
+    if let Some(position) = entry {
+        let mut folder = app.vault.list()[position].folder.as_str();
+        while !folder.is_empty() {
+            app.expanded.insert(folder.to_string());
+            folder = folder.rsplit_once('/').map_or("", |(parent, _)| parent);
+        }
+    }
+    let rows = tree_rows(app);
+    let found = rows
+        .iter()
+        .position(|row| matches!(row, TreeRow::Entry { index, .. } if Some(*index) == entry));
+    let index = match (found, rows.len().checked_sub(1)) {
+        (Some(index), _) => Some(index),
+        (None, Some(last)) => Some(password_list_state.selected().unwrap_or(0).min(last)),
+        (None, None) => None,
+    };
+    password_list_state.select(index);
+}
+
+fn handle_move_keyevent(
+    key_event: &Event<KeyEvent>,
+    password_list_state: &mut ListState,
+    app: &mut AppState,
+) {
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    let moving = match app.moving.as_mut() {
+        Some(moving) => moving,
+        None => return,
+    };
+    match event.code {
+        KeyCode::Esc => app.moving = None,
+        KeyCode::Char(c) => moving.folder.push(c),
+        KeyCode::Backspace => {
+            moving.folder.pop();
+        }
+        KeyCode::Enter => {
+            if let Some(moving) = app.moving.take() {
+                // Gone if another session deleted it, then there's nothing to move
+                if app.vault.move_to(&moving.entry, &moving.folder).is_ok() {
+                    select_entry(password_list_state, app, Some(moving.entry));
+                }
             }
         }
+        _ => {}
+    }
+}
+
+// Takes on the store as it is on disk, dropping unsaved changes
+fn reload_vault(password_list_state: &mut ListState, app: &mut AppState) {
+    let selected = selected_entry(password_list_state, app);
+    match app.vault.reload() {
+        Ok(()) => {
+            app.conflict = None;
+            select_entry(password_list_state, app, selected);
+        }
+        Err(e) => {
+            app.conflict = Some(format!(
+                "Couldn't read the store again: {}. Press r to try again or q to quit",
+                e
+            ))
+        }
     }
 }
 
@@ -338,7 +1120,6 @@ fn handle_home_keyevent(
     key_event: &Event<KeyEvent>,
     active_menu_item: &mut MenuItem,
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    app: &mut AppState,
 ) {
     match key_event {
         Event::Input(event) => match event.code {
@@ -357,6 +1138,9 @@ fn handle_home_keyevent(
             KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
             KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
             KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
+            KeyCode::Char('c') => *active_menu_item = MenuItem::ChangePassword,
+            KeyCode::Char('s') => *active_menu_item = MenuItem::Shares,
+            KeyCode::Char('o') => *active_menu_item = MenuItem::Vaults,
 
             _ => {}
         },
@@ -368,14 +1152,50 @@ fn handle_passwords_keyevent(
     key_event: &Event<KeyEvent>,
     active_menu_item: &mut MenuItem,
     password_list_state: &mut ListState,
+    input_state: &mut InputState,
     app: &mut AppState,
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
 ) {
     match key_event {
         Event::Input(event) => match event.code {
             KeyCode::Char('d') => {
-                remove_password_at_index(password_list_state, app)
-                    .expect("Couldn't remove password");
+                if let Err(e) = remove_selected_password(password_list_state, app) {
+                    app.action_error = Some(format!("Couldn't remove the entry: {}", e));
+                }
+            }
+            KeyCode::Char('v') => app.reveal = !app.reveal,
+            KeyCode::Enter => {
+                let rows = tree_rows(app);
+                if let Some(TreeRow::Folder { path, .. }) = password_list_state
+                    .selected()
+                    .and_then(|index| rows.get(index))
+                {
+                    if !app.expanded.remove(path) {
+                        app.expanded.insert(path.clone());
+                    }
+                }
+            }
+            KeyCode::Char('m') => {
+                if let Some(id) = selected_entry(password_list_state, app) {
+                    let folder = app.vault.get(&id).map(|entry| entry.folder.clone());
+                    app.moving = Some(MoveState {
+                        entry: id,
+                        folder: folder.unwrap_or_default(),
+                    });
+                }
+            }
+            KeyCode::Char('e') => {
+                if let Some(id) = selected_entry(password_list_state, app) {
+                    edit_entry(input_state, app, id);
+                    *active_menu_item = MenuItem::AddPassword;
+                }
+            }
+            KeyCode::Char(c @ '1'..='9') => {
+                if let Some(id) = selected_entry(password_list_state, app) {
+                    // A number past the end of the history does nothing
+                    let index = c as usize - '1' as usize;
+                    let _ = app.vault.restore_login(&id, index);
+                }
             }
             KeyCode::Char('q') => {
                 disable_raw_mode().expect("Raw mode was not disabled");
@@ -391,26 +1211,40 @@ fn handle_passwords_keyevent(
             }
             KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
             KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
-            KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
-            KeyCode::Char('j') => {
-                if let Some(selected) = password_list_state.selected() {
-                    let amount_passwords = read_db(app).expect("Couldn't fetch passwords").len();
-                    if selected >= amount_passwords - 1 {
-                        password_list_state.select(Some(0));
-                    } else {
-                        password_list_state.select(Some(selected + 1));
-                    }
+            KeyCode::Char('a') => {
+                // A new entry, not one left half edited
+                if input_state.entry.is_some() {
+                    clear_input(input_state);
+                }
+                // which goes in the folder being looked at
+                if input_state.input(EntryField::Folder).is_empty() {
+                    let folder = selected_folder(password_list_state, app);
+                    input_state.inputs[EntryField::Folder as usize] = folder;
                 }
+                *active_menu_item = MenuItem::AddPassword
+            }
+            KeyCode::Char('c') => *active_menu_item = MenuItem::ChangePassword,
+            KeyCode::Char('s') => *active_menu_item = MenuItem::Shares,
+            KeyCode::Char('o') => *active_menu_item = MenuItem::Vaults,
+            KeyCode::Char('j') => {
+                app.reveal = false;
+                let amount_rows = tree_rows(app).len();
+                let next = match password_list_state.selected() {
+                    _ if amount_rows == 0 => None,
+                    Some(selected) if selected + 1 < amount_rows => Some(selected + 1),
+                    _ => Some(0),
+                };
+                password_list_state.select(next);
             }
             KeyCode::Char('k') => {
-                if let Some(selected) = password_list_state.selected() {
-                    let amount_passwords = read_db(app).expect("can fetch password list").len();
-                    if selected <= 0 {
-                        password_list_state.select(Some(amount_passwords - 1));
-                    } else {
-                        password_list_state.select(Some(selected - 1));
-                    }
-                }
+                app.reveal = false;
+                let amount_rows = tree_rows(app).len();
+                let previous = match password_list_state.selected() {
+                    _ if amount_rows == 0 => None,
+                    Some(selected) if selected > 0 => Some(selected.min(amount_rows) - 1),
+                    _ => Some(amount_rows.saturating_sub(1)),
+                };
+                password_list_state.select(previous);
             }
 
             _ => {}
@@ -426,127 +1260,496 @@ fn handle_add_keyevent(
     app: &mut AppState,
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
 ) {
-    match input_state.input_mode {
-        InputMode::DomainNormal => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('i') => input_state.input_mode = InputMode::DomainEditing,
-                KeyCode::Char('j') => input_state.input_mode = InputMode::UsernameNormal,
-                KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
-                KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
-                KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
-                KeyCode::Char('q') => {
-                    disable_raw_mode().expect("Raw mode was not disabled");
-
-                    execute!(
-                        terminal.backend_mut(),
-                        LeaveAlternateScreen,
-                        DisableMouseCapture
-                    )
-                    .expect("Leaving alt screen failed");
-                    terminal.show_cursor().expect("Unable to show cursor");
-                    exit(0)
-                }
-
-                _ => {}
-            },
-            Event::Tick => {}
-        },
-        InputMode::DomainEditing => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Esc => input_state.input_mode = InputMode::DomainNormal,
-                KeyCode::Char(c) => input_state.input_domain.push(c),
-                KeyCode::Backspace => {
-                    input_state.input_domain.pop();
-                }
-                _ => {}
-            },
-            Event::Tick => {}
-        },
-        InputMode::UsernameNormal => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('i') => input_state.input_mode = InputMode::UsernameEditing,
-                KeyCode::Char('j') => input_state.input_mode = InputMode::PasswordNormal,
-                KeyCode::Char('k') => input_state.input_mode = InputMode::DomainNormal,
-                KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
-                KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
-                KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
-                KeyCode::Char('q') => {
-                    disable_raw_mode().expect("Raw mode was not disabled");
-
-                    execute!(
-                        terminal.backend_mut(),
-                        LeaveAlternateScreen,
-                        DisableMouseCapture
-                    )
-                    .expect("Leaving alt screen failed");
-                    terminal.show_cursor().expect("Unable to show cursor");
-                    exit(0);
-                }
-                _ => {}
-            },
-            Event::Tick => {}
-        },
-        InputMode::UsernameEditing => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Esc => input_state.input_mode = InputMode::UsernameNormal,
-                KeyCode::Char(c) => input_state.input_username.push(c),
-                KeyCode::Backspace => {
-                    input_state.input_username.pop();
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    if input_state.editing {
+        match event.code {
+            KeyCode::Esc => input_state.editing = false,
+            KeyCode::Tab if input_state.field == EntryField::Fields => {
+                input_state.fields.switch_part()
+            }
+            KeyCode::Char(c) => {
+                if let Some(input) = input_state.input_mut() {
+                    input.push(c);
                 }
-                _ => {}
-            },
-            Event::Tick => {}
-        },
-        InputMode::PasswordNormal => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Char('i') => input_state.input_mode = InputMode::PasswordEditing,
-                KeyCode::Char('k') => input_state.input_mode = InputMode::UsernameNormal,
-                KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
-                KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
-                KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
-                KeyCode::Char('q') => {
-                    disable_raw_mode().expect("Raw mode was not disabled");
-
-                    execute!(
-                        terminal.backend_mut(),
-                        LeaveAlternateScreen,
-                        DisableMouseCapture
-                    )
-                    .expect("Leaving alt screen failed");
-                    terminal.show_cursor().expect("Unable to show cursor");
-                    exit(0);
+            }
+            KeyCode::Backspace => {
+                if let Some(input) = input_state.input_mut() {
+                    input.pop();
                 }
+            }
+            KeyCode::Enter => submit_input(input_state, app),
+            _ => {}
+        }
+        return;
+    }
+    match event.code {
+        KeyCode::Char('i') => {
+            if input_state.field == EntryField::Fields && input_state.fields.is_empty() {
+                input_state.fields.add();
+            }
+            input_state.editing = true;
+        }
+        KeyCode::Char('j') => input_state.step(1),
+        KeyCode::Char('k') => input_state.step(-1),
+        KeyCode::Char('n') if input_state.field == EntryField::Fields => {
+            input_state.fields.add();
+            input_state.editing = true;
+        }
+        KeyCode::Char('x') if input_state.field == EntryField::Fields => {
+            input_state.fields.remove()
+        }
+        KeyCode::Char('*') if input_state.field == EntryField::Fields => {
+            input_state.fields.toggle_concealed()
+        }
+        // An entry being edited stays the kind it is
+        KeyCode::Char('t') if input_state.entry.is_none() => {
+            input_state.kind = next_kind(&input_state.kind);
+            input_state.field = EntryField::Domain;
+        }
+        KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
+        KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
+        KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
+        KeyCode::Char('c') => *active_menu_item = MenuItem::ChangePassword,
+        KeyCode::Char('s') => *active_menu_item = MenuItem::Shares,
+        KeyCode::Char('o') => *active_menu_item = MenuItem::Vaults,
+        KeyCode::Char('q') => {
+            disable_raw_mode().expect("Raw mode was not disabled");
+
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )
+            .expect("Leaving alt screen failed");
+            terminal.show_cursor().expect("Unable to show cursor");
+            exit(0);
+        }
+        KeyCode::Enter => submit_input(input_state, app),
+        _ => {}
+    }
+}
 
-                KeyCode::Enter => {
-                    add_password_to_db(input_state, app).expect("Failed to add password");
-                    clear_input(input_state);
-                }
-                _ => {}
-            },
-            Event::Tick => {}
-        },
-        InputMode::PasswordEditing => match key_event {
-            Event::Input(event) => match event.code {
-                KeyCode::Esc => input_state.input_mode = InputMode::PasswordNormal,
-                KeyCode::Char(c) => input_state.input_password.push(c),
-                KeyCode::Backspace => {
-                    input_state.input_password.pop();
-                }
-                KeyCode::Enter => {
-                    add_password_to_db(input_state, app).expect("Failed to add password");
-                    clear_input(input_state);
-                }
-                _ => {}
-            },
-            Event::Tick => {}
-        },
+fn handle_change_password_keyevent(
+    key_event: &Event<KeyEvent>,
+    active_menu_item: &mut MenuItem,
+    rekey_state: &mut RekeyState,
+    app: &mut AppState,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) {
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    if rekey_state.editing {
+        let field = match rekey_state.field {
+            RekeyField::Current => &mut rekey_state.current,
+            RekeyField::New => &mut rekey_state.new,
+            RekeyField::Confirm => &mut rekey_state.confirm,
+        };
+        match event.code {
+            KeyCode::Esc => rekey_state.editing = false,
+            KeyCode::Char(c) => field.push(c),
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Enter => submit_change_password(rekey_state, app),
+            _ => {}
+        }
+        return;
+    }
+    match event.code {
+        KeyCode::Char('i') => rekey_state.editing = true,
+        KeyCode::Char('j') => {
+            rekey_state.field = match rekey_state.field {
+                RekeyField::Current => RekeyField::New,
+                _ => RekeyField::Confirm,
+            }
+        }
+        KeyCode::Char('k') => {
+            rekey_state.field = match rekey_state.field {
+                RekeyField::Confirm => RekeyField::New,
+                _ => RekeyField::Current,
+            }
+        }
+        KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
+        KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
+        KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
+        KeyCode::Char('s') => *active_menu_item = MenuItem::Shares,
+        KeyCode::Char('o') => *active_menu_item = MenuItem::Vaults,
+        KeyCode::Char('q') => {
+            disable_raw_mode().expect("Raw mode was not disabled");
+
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )
+            .expect("Leaving alt screen failed");
+            terminal.show_cursor().expect("Unable to show cursor");
+            exit(0);
+        }
+        KeyCode::Enter => submit_change_password(rekey_state, app),
+        _ => {}
+    }
+}
+
+fn submit_change_password(rekey_state: &mut RekeyState, app: &mut AppState) {
+    if rekey_state.new.is_empty() {
+        rekey_state.message = String::from("The new master password can't be empty");
+        return;
+    }
+    if rekey_state.new != rekey_state.confirm {
+        rekey_state.message = String::from("The new passwords don't match");
+        return;
+    }
+    if app.vault.is_dirty() {
+        rekey_state.message = String::from(UNSAVED_MESSAGE);
+        return;
+    }
+    match unlock::change_master_password(
+        &app.store_path,
+        &rekey_state.current,
+        &rekey_state.new,
+        app.keyfile.as_deref().map(Vec::as_slice),
+        app.vault.backups(),
+    ) {
+        // The vault is reread for the new header, nothing unsaved is lost
+        // since it was clean before the change
+        Ok(stale) => {
+            *rekey_state = RekeyState::default();
+            rekey_state.message = match app.vault.reload() {
+                Ok(()) => String::from("Master password changed"),
+                Err(e) => format!(
+                    "Master password changed, but couldn't read the store again: {}",
+                    e
+                ),
+            };
+            if let Some(warning) = unlock::stale_backups_warning(&stale) {
+                rekey_state.message += &format!(". {}", warning);
+            }
+        }
+        Err(e) => rekey_state.message = format!("Couldn't change the master password: {}", e),
+    }
+}
+
+fn handle_shares_keyevent(
+    key_event: &Event<KeyEvent>,
+    active_menu_item: &mut MenuItem,
+    shares_state: &mut SharesState,
+    app: &mut AppState,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) {
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    if shares_state.editing {
+        let field = match shares_state.field {
+            SharesField::Threshold => &mut shares_state.threshold,
+            SharesField::Count => &mut shares_state.count,
+            SharesField::Share => &mut shares_state.share,
+        };
+        match event.code {
+            KeyCode::Esc => shares_state.editing = false,
+            KeyCode::Char(c) => field.push(c),
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Enter => submit_shares(shares_state, app),
+            _ => {}
+        }
+        return;
+    }
+    match event.code {
+        KeyCode::Char('i') => shares_state.editing = true,
+        KeyCode::Char('j') => {
+            shares_state.field = match shares_state.field {
+                SharesField::Threshold => SharesField::Count,
+                _ => SharesField::Share,
+            }
+        }
+        KeyCode::Char('k') => {
+            shares_state.field = match shares_state.field {
+                SharesField::Share => SharesField::Count,
+                _ => SharesField::Threshold,
+            }
+        }
+        KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
+        KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
+        KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
+        KeyCode::Char('c') => *active_menu_item = MenuItem::ChangePassword,
+        KeyCode::Char('o') => *active_menu_item = MenuItem::Vaults,
+        KeyCode::Char('q') => {
+            disable_raw_mode().expect("Raw mode was not disabled");
+
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )
+            .expect("Leaving alt screen failed");
+            terminal.show_cursor().expect("Unable to show cursor");
+            exit(0);
+        }
+        KeyCode::Enter => submit_shares(shares_state, app),
+        _ => {}
+    }
+}
+
+fn handle_vaults_keyevent(
+    key_event: &Event<KeyEvent>,
+    active_menu_item: &mut MenuItem,
+    vaults_state: &mut VaultsState,
+    password_list_state: &mut ListState,
+    input_state: &mut InputState,
+    app: &mut AppState,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) {
+    let event = match key_event {
+        Event::Input(event) => event,
+        Event::Tick => return,
+    };
+    if vaults_state.editing {
+        match event.code {
+            KeyCode::Esc => vaults_state.editing = false,
+            KeyCode::Char(c) => vaults_state.password.push(c),
+            KeyCode::Backspace => {
+                vaults_state.password.pop();
+            }
+            KeyCode::Enter => open_selected_vault(
+                active_menu_item,
+                vaults_state,
+                password_list_state,
+                input_state,
+                app,
+            ),
+            _ => {}
+        }
+        return;
+    }
+    let last = vaults_state.names.len().saturating_sub(1);
+    let selected = vaults_state.list_state.selected().unwrap_or(0);
+    match event.code {
+        KeyCode::Char('i') => vaults_state.editing = true,
+        KeyCode::Char('j') => vaults_state
+            .list_state
+            .select(Some((selected + 1).min(last))),
+        KeyCode::Char('k') => vaults_state
+            .list_state
+            .select(Some(selected.saturating_sub(1))),
+        KeyCode::Char('h') => *active_menu_item = MenuItem::Home,
+        KeyCode::Char('p') => *active_menu_item = MenuItem::Passwords,
+        KeyCode::Char('a') => *active_menu_item = MenuItem::AddPassword,
+        KeyCode::Char('c') => *active_menu_item = MenuItem::ChangePassword,
+        KeyCode::Char('s') => *active_menu_item = MenuItem::Shares,
+        KeyCode::Char('q') => {
+            disable_raw_mode().expect("Raw mode was not disabled");
+
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )
+            .expect("Leaving alt screen failed");
+            terminal.show_cursor().expect("Unable to show cursor");
+            exit(0);
+        }
+        KeyCode::Enter => open_selected_vault(
+            active_menu_item,
+            vaults_state,
+            password_list_state,
+            input_state,
+            app,
+        ),
+        _ => {}
+    }
+}
+
+// Swaps the open vault for the one selected and shows its passwords. Changes
+// are saved after every event, but one that failed leaves the open vault
+// with unsaved changes, and then it isn't swapped.
+fn open_selected_vault(
+    active_menu_item: &mut MenuItem,
+    vaults_state: &mut VaultsState,
+    password_list_state: &mut ListState,
+    input_state: &mut InputState,
+    app: &mut AppState,
+) {
+    let name = match vaults_state
+        .list_state
+        .selected()
+        .and_then(|index| vaults_state.names.get(index))
+    {
+        Some(name) => name.clone(),
+        None => return,
+    };
+    if name == app.vault_name {
+        vaults_state.message = format!("{} is already open", name);
+        return;
+    }
+    if app.vault.is_dirty() {
+        vaults_state.message = String::from(UNSAVED_MESSAGE);
+        return;
+    }
+    let store_path = stores::path(Path::new(&app.config_dir), &name)
+        .to_string_lossy()
+        .into_owned();
+    let keyfile = app.keyfile.as_deref().map(Vec::as_slice);
+    let mut vault = match unlock_vault(&store_path, &vaults_state.password, keyfile) {
+        Ok(vault) => vault,
+        Err(e) => {
+            vaults_state.message = format!("Couldn't open {}: {}", name, e);
+            return;
+        }
+    };
+    vaults_state.password.zeroize();
+    vaults_state.editing = false;
+    vault.set_backups(app.vault.backups());
+    vault.set_history_depth(app.vault.history_depth());
+    app.vault = vault;
+    app.store_path = store_path;
+    app.vault_name = name;
+    app.expanded.clear();
+    app.reveal = false;
+    app.moving = None;
+    // The form may hold an entry of the vault just closed
+    clear_input(input_state);
+    password_list_state.select(Some(0));
+    vaults_state.message = format!("Opened {}", app.vault_name);
+    *active_menu_item = MenuItem::Passwords;
+}
+
+// Opens the store at `store_path` with a master password typed into the
+// interface. A store without a header is one from before master passwords,
+// which only starting arustylock with it upgrades.
+fn unlock_vault(store_path: &str, password: &str, keyfile: Option<&[u8]>) -> Result<Vault, Error> {
+    match Vault::unlock(store_path, password, keyfile) {
+        Ok(vault) => Ok(vault),
+        Err(VaultError::WrongKey) if keyfile.is_some() => Err(Error::WrongPasswordOrKeyfile),
+        Err(VaultError::WrongKey) => Err(Error::WrongPassword),
+        Err(VaultError::Corrupted) if is_legacy(&fs::read(store_path)?) => Err(Error::NeedsUpgrade),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn submit_shares(shares_state: &mut SharesState, app: &mut AppState) {
+    if shares_state.field == SharesField::Share {
+        add_share(shares_state, app);
+        return;
+    }
+    let (threshold, count) = match (
+        shares_state.threshold.trim().parse(),
+        shares_state.count.trim().parse(),
+    ) {
+        (Ok(threshold), Ok(count)) => (threshold, count),
+        _ => {
+            shares_state.message =
+                String::from("The threshold and count must be numbers up to 255");
+            return;
+        }
+    };
+    // Picks up key slots added from the command line since the store was
+    // read, which would drop changes that aren't saved yet
+    if app.vault.is_dirty() {
+        shares_state.message = String::from(UNSAVED_MESSAGE);
+        return;
+    }
+    if let Err(e) = app.vault.reload() {
+        shares_state.message = format!("Couldn't read the store again: {}", e);
+        return;
+    }
+    match unlock::split_recovery_key(
+        &app.store_path,
+        app.vault.key(),
+        app.vault.header(),
+        threshold,
+        count,
+        app.vault.backups(),
+    ) {
+        Ok((shares, stale)) => {
+            shares_state.message = format!(
+                "The recovery key was replaced by one split into {} shares, any {} of which recover the store. Hand each share to a different person",
+                count, threshold
+            );
+            if let Err(e) = app.vault.reload() {
+                shares_state.message += &format!(". Couldn't read the store again: {}", e);
+            }
+            if let Some(warning) = unlock::stale_backups_warning(&stale) {
+                shares_state.message += &format!(". {}", warning);
+            }
+            shares_state.output.zeroize();
+            shares_state.output = shares
+                .iter()
+                .map(|share| format!("Share {}: {}", share.share.x, share.words().join(" ")))
+                .collect();
+        }
+        Err(e) => shares_state.message = format!("Couldn't split the recovery key: {}", e),
+    }
+}
+
+// Collects shares until there are enough to put the recovery key back
+// together, then checks it against the store
+fn add_share(shares_state: &mut SharesState, app: &mut AppState) {
+    let share = match RecoveryPhrase::parse(&shares_state.share) {
+        Ok(RecoveryPhrase::Share(share)) => share,
+        Ok(RecoveryPhrase::Key(_)) => {
+            shares_state.message = String::from("That's a whole recovery key, not a share");
+            return;
+        }
+        Err(e) => {
+            shares_state.message = format!("Couldn't read the share: {}", e);
+            return;
+        }
+    };
+    shares_state.share.zeroize();
+    if shares_state
+        .entered
+        .iter()
+        .any(|other| other.share.x == share.share.x)
+    {
+        shares_state.message = String::from("That share was already entered");
+        return;
+    }
+    shares_state.entered.push(share);
+    let threshold = shares_state.entered[0].threshold as usize;
+    if shares_state.entered.len() < threshold {
+        shares_state.message = format!(
+            "{} of {} shares entered",
+            shares_state.entered.len(),
+            threshold
+        );
+        return;
+    }
+
+    let combined = RecoveryKey::combine(&shares_state.entered);
+    shares_state.entered.clear();
+    match combined {
+        Ok(recovery_key)
+            if app
+                .vault
+                .header()
+                .unlock_with_recovery_key(&recovery_key)
+                .is_ok() =>
+        {
+            shares_state.message = String::from("The shares give back this store's recovery key");
+            shares_state.output.zeroize();
+            shares_state.output = vec![recovery_key.words().join(" ")];
+        }
+        Ok(_) => {
+            shares_state.message =
+                String::from("The shares don't give back this store's recovery key")
+        }
+        Err(e) => shares_state.message = format!("Couldn't combine the shares: {}", e),
     }
 }
 
 fn clear_input(input_state: &mut InputState) {
-    input_state.input_domain = String::new();
-    input_state.input_username = String::new();
-    input_state.input_password = String::new();
+    input_state.inputs.iter_mut().for_each(Zeroize::zeroize);
+    input_state.fields.clear();
+    input_state.entry = None;
 }
 
 fn render_home<'a>() -> Paragraph<'a> {
@@ -561,7 +1764,7 @@ fn render_home<'a>() -> Paragraph<'a> {
             Style::default().fg(Color::LightBlue),
         )]),
         Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("Press 'p' to access passwords, 'a' to add a new password and 'd' to delete the currently selected password.")]),
+        Spans::from(vec![Span::raw("Press 'p' to access passwords, 'a' to add a new password, 'e' to edit the currently selected password, 'v' to show its concealed fields and previous passwords, 'm' to move it to another folder, 'd' to delete it, 1-9 to put back one of its previous logins, 'c' to change the master password and 's' to split the recovery key into shares and 'o' to open another vault.")]),
     ])
     .alignment(Alignment::Center)
     .block(
@@ -574,36 +1777,41 @@ fn render_home<'a>() -> Paragraph<'a> {
     home
 }
 
+// The detail borrows the selected entry from `app` rather than copying its
+// secrets into strings that would be left behind each frame
 fn render_passwords<'a>(
     password_list_state: &ListState,
-    app: &mut AppState,
+    app: &'a AppState,
 ) -> (List<'a>, Table<'a>) {
     let passwords = Block::default()
         .borders(Borders::ALL)
         .style(Style::default().fg(Color::White))
-        .title("Passwords")
+        .title(format!("Passwords in {}", app.vault_name))
         .border_type(BorderType::Plain);
 
-    let password_list = read_db(app).expect("Couldn't fetch passwords list");
-    let items: Vec<_> = password_list
+    let password_list = app.vault.list();
+    let rows = tree_rows(app);
+    let items: Vec<_> = rows
         .iter()
-        .map(|password| {
-            ListItem::new(Spans::from(vec![Span::styled(
-                password.domain.clone(),
-                Style::default(),
-            )]))
+        .map(|row| {
+            let line = match row {
+                TreeRow::Folder { path, depth } => {
+                    let name = path.rsplit('/').next().unwrap_or(path);
+                    let marker = if app.expanded.contains(path) {
+                        "v"
+                    } else {
+                        ">"
+                    };
+                    format!("{}{} {}/", "  ".repeat(*depth), marker, name)
+                }
+                TreeRow::Entry { index, depth } => {
+                    format!("{}{}", "  ".repeat(*depth), password_list[*index].domain)
+                }
+            };
+            ListItem::new(Spans::from(vec![Span::styled(line, Style::default())]))
         })
         .collect();
 
-    let selected_password = password_list
-        .get(
-            password_list_state
-                .selected()
-                .expect("Couldn't get selected password"),
-        )
-        .expect("Error getting selected password")
-        .clone();
-
     let list = List::new(items).block(passwords).highlight_style(
         Style::default()
             .bg(Color::Yellow)
@@ -611,143 +1819,555 @@ fn render_passwords<'a>(
             .add_modifier(Modifier::BOLD),
     );
 
-    let password_detail = Table::new(vec![Row::new(vec![
-        Cell::from(Span::raw(selected_password.domain)),
-        Cell::from(Span::raw(selected_password.username)),
-        Cell::from(Span::raw(selected_password.password)),
-    ])])
-    .header(Row::new(vec![
-        Cell::from(Span::styled(
-            "Domain",
-            Style::default().add_modifier(Modifier::BOLD),
-        )),
-        Cell::from(Span::styled(
-            "Username",
-            Style::default().add_modifier(Modifier::BOLD),
-        )),
-        Cell::from(Span::styled(
-            "Password",
-            Style::default().add_modifier(Modifier::BOLD),
-        )),
-    ]))
-    .block(
-        Block::default()
-            .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White))
-            .title("Detail")
-            .border_type(BorderType::Plain),
-    )
-    .widths(&[
-        Constraint::Percentage(33),
-        Constraint::Percentage(33),
-        Constraint::Percentage(33),
+    let detail = |name: &'a str, value: Cow<'a, str>| {
+        Row::new(vec![
+            Cell::from(Span::styled(
+                name,
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::raw(value)),
+        ])
+    };
+    let detail_block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White))
+        .title("Detail")
+        .border_type(BorderType::Plain);
+    const WIDTHS: &[Constraint] = &[Constraint::Percentage(15), Constraint::Percentage(85)];
+
+    let selected = password_list_state
+        .selected()
+        .and_then(|index| rows.get(index));
+    let selected_password = match selected {
+        Some(TreeRow::Entry { index, .. }) => &password_list[*index],
+        Some(TreeRow::Folder { path, .. }) => {
+            let below = format!("{}/", path);
+            let count = password_list
+                .iter()
+                .filter(|entry| entry.folder == *path || entry.folder.starts_with(&below))
+                .count();
+            let folder_detail = Table::new(vec![
+                detail("Folder", path.clone().into()),
+                detail("Entries", count.to_string().into()),
+                detail("", "Enter opens or closes the folder".into()),
+            ])
+            .block(detail_block)
+            .widths(WIDTHS);
+            return (list, folder_detail);
+        }
+        // Nothing to show in an empty store
+        None => {
+            let hint = if rows.is_empty() {
+                "No entries, press 'a' to add one"
+            } else {
+                ""
+            };
+            let empty_detail = Table::new(vec![detail("", hint.into())])
+                .block(detail_block)
+                .widths(WIDTHS);
+            return (list, empty_detail);
+        }
+    };
+    // Entries from before times were kept have none
+    let time = |time: Option<DateTime<Utc>>| match time {
+        Some(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => String::from("unknown"),
+    };
+    let conceal = |value: &'a str| -> Cow<'a, str> {
+        if app.reveal {
+            value.into()
+        } else {
+            "******** (v to show)".into()
+        }
+    };
+    let name: Cow<'a, str> = selected_password.domain.as_str().into();
+    let mut rows = vec![detail("Type", selected_password.item.name().into())];
+    if !selected_password.folder.is_empty() {
+        rows.push(detail("Folder", selected_password.folder.as_str().into()));
+    }
+    match &selected_password.item {
+        Item::Login(login) => rows.extend(vec![
+            detail("Domain", name),
+            detail("Username", login.username.as_str().into()),
+            detail("Password", login.password.as_str().into()),
+            detail("URLs", login.urls.join(" ").into()),
+        ]),
+        Item::Note => rows.push(detail("Name", name)),
+        Item::Card(card) => {
+            // Enough of the number to tell cards apart
+            let number = if app.reveal {
+                card.number.as_str().into()
+            } else {
+                let digits = card.number.chars().filter(char::is_ascii_digit);
+                let last: String = digits
+                    .clone()
+                    .skip(digits.count().saturating_sub(4))
+                    .collect();
+                format!("**** {} (v to show)", last).into()
+            };
+            rows.extend(vec![
+                detail("Name", name),
+                detail("Cardholder", card.holder.as_str().into()),
+                detail("Number", number),
+                detail("Expires", card.expiry.as_str().into()),
+                detail("Security code", conceal(&card.security_code)),
+            ]);
+        }
+        Item::Identity(identity) => rows.extend(vec![
+            detail("Name", name),
+            detail("Full name", identity.full_name.as_str().into()),
+            detail("Email", identity.email.as_str().into()),
+            detail("Phone", identity.phone.as_str().into()),
+            detail("Address", identity.address.as_str().into()),
+        ]),
+        Item::SshKey(key) => {
+            rows.extend(vec![
+                detail("Name", name),
+                detail("Public key", key.public_key.as_str().into()),
+            ]);
+            // The whole key file, a line per row
+            let private_key = conceal(&key.private_key);
+            let height = private_key.lines().count().max(1) as u16;
+            rows.push(
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "Private key",
+                        Style::default().add_modifier(Modifier::BOLD),
+                    )),
+                    Cell::from(Text::raw(private_key)),
+                ])
+                .height(height),
+            );
+            rows.push(detail("Passphrase", conceal(&key.passphrase)));
+        }
+    }
+    rows.push(detail("Tags", selected_password.tags.join(", ").into()));
+    for field in &selected_password.fields {
+        let value = if field.concealed {
+            conceal(&field.value)
+        } else {
+            field.value.as_str().into()
+        };
+        rows.push(Row::new(vec![
+            Cell::from(Span::styled(
+                field.name.as_str(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(Span::raw(value)),
+        ]));
+    }
+    rows.extend(vec![
+        detail("Notes", selected_password.notes.as_str().into()),
+        detail("Created", time(selected_password.created).into()),
+        detail("Modified", time(selected_password.modified).into()),
     ]);
+    // Numbered for putting them back with the number keys. Old passwords
+    // may still work somewhere, so they are masked like concealed fields
+    for (number, login) in selected_password.history.iter().enumerate() {
+        let password = if app.reveal {
+            login.password.as_str()
+        } else {
+            "********"
+        };
+        let line = Spans::from(vec![
+            Span::raw(format!("{}. ", number + 1)),
+            Span::raw(login.username.as_str()),
+            Span::raw(" / "),
+            Span::raw(password),
+            Span::raw(format!(", until {}", time(Some(login.replaced)))),
+        ]);
+        rows.push(Row::new(vec![
+            Cell::from(Span::styled(
+                if number == 0 { "History" } else { "" },
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Cell::from(line),
+        ]));
+    }
+    let password_detail = Table::new(rows).block(detail_block).widths(WIDTHS);
 
     (list, password_detail)
 }
 
-fn render_create_password<'a>(
-    input_state: &'a InputState,
-) -> (Paragraph<'a>, Paragraph<'a>, Paragraph<'a>) {
-    let domain_input = Paragraph::new(input_state.input_domain.as_ref())
-        .style(match input_state.input_mode {
-            InputMode::DomainNormal => Style::default().fg(Color::Yellow),
-            InputMode::DomainEditing => Style::default().fg(Color::Green),
-            _ => Style::default().fg(Color::White),
-        })
-        .block(Block::default().borders(Borders::ALL).title("Domain"));
+fn render_create_password<'a>(input_state: &'a InputState) -> Vec<Paragraph<'a>> {
+    let kind = &input_state.kind;
+    let type_title = match input_state.entry {
+        Some(_) => "Type (editing an entry)",
+        None => "Type, 't' to change",
+    };
+    // The folder comes first among the fields, right after the type
+    let mut paragraphs = vec![Paragraph::new(kind.name())
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().borders(Borders::ALL).title(type_title))];
+    paragraphs.extend(form_fields(kind).iter().map(|&field| {
+        let style = if input_state.field != field {
+            Style::default().fg(Color::White)
+        } else if input_state.editing {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default().fg(Color::Yellow)
+        };
+        let title = match (&input_state.entry, field) {
+            (Some(_), EntryField::PrivateKey) => {
+                String::from("Private key file, leave empty to keep the current key")
+            }
+            (_, EntryField::Fields) if input_state.editing && input_state.field == field => {
+                let part = match input_state.fields.part() {
+                    FieldPart::Name => "name",
+                    FieldPart::Value => "value",
+                };
+                format!("Custom fields, typing the {}, Tab to switch", part)
+            }
+            _ => String::from(field.title(kind)),
+        };
+        let text = match field {
+            EntryField::Fields => render_custom_fields(input_state),
+            _ => Text::from(input_state.input(field)),
+        };
+        Paragraph::new(text)
+            .style(style)
+            .block(Block::default().borders(Borders::ALL).title(title))
+    }));
+    let message = if input_state.message.is_empty() {
+        "Press 'i' to edit a field, j and k to move between them and Enter to save"
+    } else {
+        input_state.message.as_str()
+    };
+    paragraphs.push(
+        Paragraph::new(message)
+            .style(Style::default().fg(Color::LightCyan))
+            .block(Block::default().borders(Borders::ALL).title("Status")),
+    );
+    paragraphs
+}
 
-    let username_input = Paragraph::new(input_state.input_username.as_ref())
-        .style(match input_state.input_mode {
-            InputMode::UsernameNormal => Style::default().fg(Color::Yellow),
-            InputMode::UsernameEditing => Style::default().fg(Color::Green),
-            _ => Style::default().fg(Color::White),
+// A line for each custom field, the selected one underlined while the
+// custom fields are being edited
+fn render_custom_fields(input_state: &InputState) -> Text<'static> {
+    let (lines, selected) = input_state.fields.lines();
+    let on_fields = input_state.field == EntryField::Fields;
+    let spans: Vec<Spans> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let style = if on_fields && index == selected {
+                Style::default().add_modifier(Modifier::UNDERLINED)
+            } else {
+                Style::default()
+            };
+            Spans::from(Span::styled(line, style))
         })
-        .block(Block::default().borders(Borders::ALL).title("Username"));
+        .collect();
+    Text::from(spans)
+}
+
+fn render_change_password<'a>(
+    rekey_state: &RekeyState,
+) -> (Paragraph<'a>, Paragraph<'a>, Paragraph<'a>, Paragraph<'a>) {
+    let masked_input = |value: &str, field: RekeyField, title: &'a str| {
+        let style = if rekey_state.field != field {
+            Style::default().fg(Color::White)
+        } else if rekey_state.editing {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default().fg(Color::Yellow)
+        };
+        Paragraph::new("*".repeat(value.chars().count()))
+            .style(style)
+            .block(Block::default().borders(Borders::ALL).title(title))
+    };
+
+    let message = Paragraph::new(rekey_state.message.clone())
+        .style(Style::default().fg(Color::LightCyan))
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+
+    (
+        masked_input(
+            &rekey_state.current,
+            RekeyField::Current,
+            "Current master password",
+        ),
+        masked_input(&rekey_state.new, RekeyField::New, "New master password"),
+        masked_input(
+            &rekey_state.confirm,
+            RekeyField::Confirm,
+            "Confirm new master password",
+        ),
+        message,
+    )
+}
 
-    let password_input = Paragraph::new(input_state.input_password.as_ref())
-        .style(match input_state.input_mode {
-            InputMode::PasswordNormal => Style::default().fg(Color::Yellow),
-            InputMode::PasswordEditing => Style::default().fg(Color::Green),
-            _ => Style::default().fg(Color::White),
+fn render_vaults<'a>(
+    vaults_state: &VaultsState,
+    app: &AppState,
+) -> (List<'a>, Paragraph<'a>, Paragraph<'a>) {
+    let items: Vec<_> = vaults_state
+        .names
+        .iter()
+        .map(|name| {
+            let line = if *name == app.vault_name {
+                format!("{} (open)", name)
+            } else {
+                name.clone()
+            };
+            ListItem::new(Spans::from(vec![Span::raw(line)]))
         })
-        .block(Block::default().borders(Borders::ALL).title("Password"));
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Vaults"))
+        .highlight_style(
+            Style::default()
+                .bg(Color::Yellow)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        );
+
+    let style = if vaults_state.editing {
+        Style::default().fg(Color::Green)
+    } else {
+        Style::default().fg(Color::Yellow)
+    };
+    let password = Paragraph::new("*".repeat(vaults_state.password.chars().count()))
+        .style(style)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Master password of the vault"),
+        );
 
-    return (domain_input, username_input, password_input);
+    let message = Paragraph::new(vec![
+        Spans::from(vec![Span::raw(vaults_state.message.clone())]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw(
+            "To make a new vault, start arustylock with --vault NAME",
+        )]),
+    ])
+    .style(Style::default().fg(Color::LightCyan))
+    .wrap(Wrap { trim: false })
+    .block(Block::default().borders(Borders::ALL).title("Status"));
+
+    (list, password, message)
 }
 
-fn read_db(app: &mut AppState) -> Result<Vec<Password>, Error> {
-    let mut store = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(&app.store_path)
-        .unwrap();
-    let data = decrypt_data(&mut store, &app.secret_key);
-    let parsed: Vec<Password> = serde_json::from_str(from_utf8(&data).unwrap())?;
-    Ok(parsed)
+fn render_shares<'a>(
+    shares_state: &'a SharesState,
+) -> (Paragraph<'a>, Paragraph<'a>, Paragraph<'a>, Paragraph<'a>) {
+    let input = |value: &'a str, field: SharesField, title: &'a str| {
+        let style = if shares_state.field != field {
+            Style::default().fg(Color::White)
+        } else if shares_state.editing {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default().fg(Color::Yellow)
+        };
+        Paragraph::new(value)
+            .style(style)
+            .block(Block::default().borders(Borders::ALL).title(title))
+    };
+
+    let mut lines = vec![
+        Spans::from(vec![Span::raw(shares_state.message.as_str())]),
+        Spans::from(vec![Span::raw("")]),
+    ];
+    lines.extend(
+        shares_state
+            .output
+            .iter()
+            .map(|line| Spans::from(vec![Span::raw(line.as_str())])),
+    );
+    let output = Paragraph::new(lines)
+        .style(Style::default().fg(Color::LightCyan))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+
+    (
+        input(&shares_state.threshold, SharesField::Threshold, "Threshold"),
+        input(&shares_state.count, SharesField::Count, "Number of shares"),
+        input(&shares_state.share, SharesField::Share, "Share"),
+        output,
+    )
 }
 
-fn add_password_to_db(
-    input_state: &InputState,
-    app: &mut AppState,
-) -> Result<Vec<Password>, Error> {
-    let mut store = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(&app.store_path)
-        .unwrap();
-    let data = decrypt_data(&mut store, &app.secret_key);
-    let mut parsed: Vec<Password> = serde_json::from_str(from_utf8(&data).unwrap())?;
-    let new_password = Password {
-        domain: input_state.input_domain.clone(),
-        username: input_state.input_username.clone(),
-        password: input_state.input_password.clone(),
+fn open_vault(store_path: &str, secret_key: &SecretKey) -> Result<Vault, Error> {
+    Ok(Vault::open(store_path, secret_key)?)
+}
+
+fn save_vault(vault: &mut Vault) -> Result<(), Error> {
+    let json = vault.to_plaintext()?;
+    vault.write_plaintext(&json)?;
+    Ok(())
+}
+
+// The form is kept as it is when the entry can't be saved, so it can be fixed
+fn submit_input(input_state: &mut InputState, app: &mut AppState) {
+    match save_input(input_state, app) {
+        Ok(()) => {
+            clear_input(input_state);
+            input_state.message = String::from("Saved");
+        }
+        Err(message) => input_state.message = message,
+    }
+}
+
+// Fills the form with the entry with ID `id`, so saving it changes the entry
+fn edit_entry(input_state: &mut InputState, app: &AppState, id: EntryId) {
+    let entry = match app.vault.get(&id) {
+        Some(entry) => entry,
+        None => return,
     };
-    parsed.push(new_password);
-    // Convert this into Vec<u8> and then encrypt it and then write it to the store
-    let json_string: String = serde_json::to_string(&parsed).unwrap();
-    let slice: &[u8] = json_string.as_bytes();
-    let cipher_text = aead::seal(&app.secret_key, &slice).unwrap();
+    clear_input(input_state);
+    let mut set =
+        |field: EntryField, value: &str| input_state.inputs[field as usize].push_str(value);
+    set(EntryField::Folder, &entry.folder);
+    set(EntryField::Domain, &entry.domain);
+    set(EntryField::Tags, &entry.tags.join(", "));
+    set(EntryField::Notes, &entry.notes);
+    match &entry.item {
+        Item::Login(login) => {
+            set(EntryField::Username, &login.username);
+            set(EntryField::Password, &login.password);
+            set(EntryField::Urls, &login.urls.join(" "));
+        }
+        Item::Note => {}
+        Item::Card(card) => {
+            set(EntryField::Holder, &card.holder);
+            set(EntryField::Number, &card.number);
+            set(EntryField::Expiry, &card.expiry);
+            set(EntryField::SecurityCode, &card.security_code);
+        }
+        Item::Identity(identity) => {
+            set(EntryField::FullName, &identity.full_name);
+            set(EntryField::Email, &identity.email);
+            set(EntryField::Phone, &identity.phone);
+            set(EntryField::Address, &identity.address);
+        }
+        // The private key stays as it is unless another file is given
+        Item::SshKey(key) => {
+            set(EntryField::PublicKey, &key.public_key);
+            set(EntryField::Passphrase, &key.passphrase);
+        }
+    }
+    input_state.fields = FieldsForm::new(&entry.fields);
+    input_state.kind = Item::kinds()
+        .iter()
+        .find(|kind| kind.same_kind(&entry.item))
+        .cloned()
+        .unwrap_or_default();
+    input_state.field = EntryField::Domain;
+    input_state.editing = false;
+    input_state.entry = Some(id);
+}
 
-    // fs::write(&app.store_path, &serde_json::to_vec(&parsed)?)?;
-    reset_file_cursor(&mut store);
-    store.set_len(0).unwrap();
-    store.write_all(&cipher_text).unwrap();
-    // File is already encrypted at this point, so it's redundant
-    Ok(parsed)
+// Adds the entry in the form, or changes the one being edited. One deleted
+// by another session in the meantime is added again rather than lost. Fails
+// with a message for the user if the entry isn't valid.
+fn save_input(input_state: &InputState, app: &mut AppState) -> Result<(), String> {
+    let kind = &input_state.kind;
+    // Anything typed into fields of another kind before switching is left out
+    let input = |field: EntryField| {
+        if form_fields(kind).contains(&field) {
+            input_state.input(field).to_string()
+        } else {
+            String::new()
+        }
+    };
+    let mut password = input_state
+        .entry
+        .as_ref()
+        .and_then(|id| app.vault.get(id))
+        .cloned()
+        .unwrap_or_default();
+    password.item = match kind {
+        Item::Login(_) => Item::Login(Login {
+            username: input(EntryField::Username),
+            password: input(EntryField::Password),
+            urls: input(EntryField::Urls)
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        }),
+        Item::Note => Item::Note,
+        Item::Card(_) => Item::Card(Card {
+            holder: input(EntryField::Holder),
+            number: input(EntryField::Number),
+            expiry: input(EntryField::Expiry),
+            security_code: input(EntryField::SecurityCode),
+        }),
+        Item::Identity(_) => Item::Identity(Identity {
+            full_name: input(EntryField::FullName),
+            email: input(EntryField::Email),
+            phone: input(EntryField::Phone),
+            address: input(EntryField::Address),
+        }),
+        Item::SshKey(_) => Item::SshKey(read_ssh_key(input_state, &password.item)?),
+    };
+    password
+        .item
+        .validate()
+        .map_err(|e| format!("Not saved, {}", e))?;
+    password.folder = input(EntryField::Folder);
+    password.domain = input(EntryField::Domain);
+    password.fields = input_state.fields.fields();
+    password.notes = input(EntryField::Notes);
+    password.tags = input(EntryField::Tags)
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect();
+    match input_state.entry.clone() {
+        Some(id) if app.vault.get(&id).is_some() => {
+            app.vault
+                .update(&id, password)
+                .expect("The entry was just looked up");
+        }
+        _ => {
+            app.vault.insert(password);
+        }
+    }
+    Ok(())
+}
+
+// The key in the file the form names, or the one the entry already has if
+// it names none. The public key comes from the `.pub` file next to the key
+// unless it was typed in.
+fn read_ssh_key(input_state: &InputState, current: &Item) -> Result<SshKey, String> {
+    let path = input_state.input(EntryField::PrivateKey).trim();
+    let mut public_key = input_state.input(EntryField::PublicKey).trim().to_string();
+    let private_key = match current {
+        Item::SshKey(key) if path.is_empty() => key.private_key.clone(),
+        _ if path.is_empty() => return Err(String::from("Enter the path of the private key file")),
+        _ => {
+            let key = fs::read_to_string(path)
+                .map_err(|e| format!("Couldn't read the key file {}: {}", path, e))?;
+            if public_key.is_empty() {
+                if let Ok(line) = fs::read_to_string(format!("{}.pub", path)) {
+                    public_key = line.trim().to_string();
+                }
+            }
+            key
+        }
+    };
+    Ok(SshKey {
+        private_key,
+        public_key,
+        passphrase: input_state.input(EntryField::Passphrase).to_string(),
+    })
 }
 
-fn remove_password_at_index(
+fn remove_selected_password(
     password_list_state: &mut ListState,
     app: &mut AppState,
 ) -> Result<(), Error> {
-    // This is a workaround to prevent the program from crashing after removing
-    // the last password
-    let password_list = read_db(app).expect("can fetch password list");
-    if password_list.len() > 1 {
-        if let Some(selected) = password_list_state.selected() {
-            let mut store = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&app.store_path)
-                .unwrap();
-            let data = decrypt_data(&mut store, &app.secret_key);
-            let mut parsed: Vec<Password> = serde_json::from_str(from_utf8(&data).unwrap())?;
-
-            parsed.remove(selected);
-
-            let json_string: String = serde_json::to_string(&parsed).unwrap();
-            let slice: &[u8] = json_string.as_bytes();
-            let cipher_text = aead::seal(&app.secret_key, &slice).unwrap();
-
-            //fs::write(&app.store_path, &serde_json::to_vec(&parsed)?)?;
-            reset_file_cursor(&mut store);
-            store.set_len(0).unwrap();
-            store.write_all(&cipher_text).unwrap();
-            if selected > 0 {
-                password_list_state.select(Some(selected - 1));
-            }
-        }
+    if let Some(id) = selected_entry(password_list_state, app) {
+        app.vault.delete(&id)?;
+        // The row above, kept in the tree, which may now be empty
+        let selected = password_list_state.selected().unwrap_or(0);
+        password_list_state.select(Some(selected.saturating_sub(1)));
+        select_entry(password_list_state, app, None);
     }
     Ok(())
 }
@@ -0,0 +1,55 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Several password stores can live in the config directory, each with its
+// own key slots. The first one, `data`, is called "default"; the others are
+// kept under `vaults/` by their name, with their backups and lock files next
+// to them.
+
+/// The name of the store at `data`.
+pub const DEFAULT: &str = "default";
+
+const VAULTS_DIR: &str = "vaults";
+
+/// Whether `name` can name a store: letters, digits, `-` and `_`, so it is
+/// safe as a file name and can't be mistaken for a backup or lock file.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Where the store called `name` is kept in `config_dir`.
+pub fn path(config_dir: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT {
+        config_dir.join("data")
+    } else {
+        config_dir.join(VAULTS_DIR).join(name)
+    }
+}
+
+/// The names of the stores in `config_dir`, the default one first if there
+/// is one and the others sorted.
+pub fn list(config_dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let dir = config_dir.join(VAULTS_DIR);
+    let entries = if dir.is_dir() {
+        fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if valid_name(&name) && name != DEFAULT && entry.file_type()?.is_file() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    if path(config_dir, DEFAULT).exists() {
+        names.insert(0, String::from(DEFAULT));
+    }
+    Ok(names)
+}
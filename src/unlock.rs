@@ -0,0 +1,782 @@
+// Everything that happens before the TUI starts: asking for the master
+// password, opening or upgrading the store, and the subcommands that manage
+// its key slots and backups, or list its entries.
+
+use crate::cli::NewSlot;
+use crate::Error;
+use arustylock::atomic::replace_file;
+use arustylock::backup::{self, Backup};
+use arustylock::encryption::encryption::{open, replace_header, seal};
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::keyslot::{KdfParams, KeySlot, SlotKind};
+use arustylock::encryption::legacy::open_legacy;
+use arustylock::encryption::recovery::{RecoveryKey, RecoveryPhrase, RecoveryShare};
+use arustylock::lock::StoreLock;
+use arustylock::stores;
+use arustylock::vault::{folder_path, StoreError};
+use chrono::Local;
+use crossterm::{
+    event::{self, Event as CEvent, KeyCode, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use orion::aead::SecretKey;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::process::exit;
+use zeroize::Zeroizing;
+
+const MAX_UNLOCK_ATTEMPTS: usize = 3;
+
+// Room for a recovery share typed in full, so typing doesn't grow the buffer
+// and leave partial copies of the secret behind
+const PROMPT_CAPACITY: usize = 256;
+
+// Reads a line from the terminal without echoing it back
+pub fn prompt_password(prompt: &str) -> crossterm::Result<Zeroizing<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    enable_raw_mode()?;
+    let mut password = Zeroizing::new(String::with_capacity(PROMPT_CAPACITY));
+    loop {
+        if let CEvent::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Enter => break,
+                KeyCode::Esc => {
+                    disable_raw_mode()?;
+                    println!();
+                    exit(1);
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    disable_raw_mode()?;
+                    println!();
+                    exit(1);
+                }
+                KeyCode::Char(c) => password.push(c),
+                KeyCode::Backspace => {
+                    password.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    disable_raw_mode()?;
+    println!();
+    Ok(password)
+}
+
+// Asks for a new secret twice until both entries match
+fn choose_secret(name: &str) -> Zeroizing<String> {
+    loop {
+        let secret = prompt_password(&format!("Choose a {}: ", name))
+            .expect("Couldn't read from the terminal");
+        if secret.is_empty() {
+            println!("The {} can't be empty", name);
+            continue;
+        }
+        let confirm = prompt_password(&format!("Confirm {}: ", name))
+            .expect("Couldn't read from the terminal");
+        if secret == confirm {
+            return secret;
+        }
+        println!("Entries didn't match, try again");
+    }
+}
+
+pub fn choose_master_password() -> Zeroizing<String> {
+    choose_secret("master password")
+}
+
+pub fn read_keyfile(path: &str) -> Zeroizing<Vec<u8>> {
+    match fs::read(path).map(Zeroizing::new) {
+        Ok(contents) if !contents.is_empty() => contents,
+        Ok(_) => {
+            eprintln!("The keyfile {} is empty", path);
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("Couldn't read the keyfile {}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+fn fail(message: &str, store_path: &str) -> ! {
+    eprintln!("{} at {}", message, store_path);
+    exit(1);
+}
+
+/// What to tell the user about backups that `write_header` couldn't give the
+/// new key slots, if there are any.
+pub fn stale_backups_warning(stale: &[Backup]) -> Option<String> {
+    if stale.is_empty() {
+        return None;
+    }
+    let paths: Vec<String> = stale
+        .iter()
+        .map(|backup| backup.path.display().to_string())
+        .collect();
+    Some(format!(
+        "These backups couldn't be updated and still open with the old key slots, remove them if that matters: {}",
+        paths.join(", ")
+    ))
+}
+
+fn warn_stale_backups(stale: &[Backup]) {
+    if let Some(warning) = stale_backups_warning(stale) {
+        eprintln!("{}", warning);
+    }
+}
+
+// Makes sure some key slot can be opened with the factors at hand
+fn check_factors(header: &VaultHeader, keyfile: Option<&[u8]>) -> Result<(), Error> {
+    let has_keyfile = keyfile.is_some();
+    if header.accepts(true, has_keyfile) || (has_keyfile && header.accepts(false, true)) {
+        Ok(())
+    } else if has_keyfile {
+        Err(Error::KeyfileNotUsed)
+    } else {
+        Err(Error::KeyfileRequired)
+    }
+}
+
+fn wrong_password_error(keyfile: Option<&[u8]>) -> Error {
+    if keyfile.is_some() {
+        Error::WrongPasswordOrKeyfile
+    } else {
+        Error::WrongPassword
+    }
+}
+
+fn print_wrong_password(keyfile: Option<&[u8]>) {
+    if keyfile.is_some() {
+        println!("Wrong master password or keyfile");
+    } else {
+        println!("Wrong master password");
+    }
+}
+
+/// Generates the data key for a new store and wraps it in a slot for the
+/// master password, and the keyfile if one was given.
+pub fn new_store_key(
+    password: &str,
+    keyfile: Option<&[u8]>,
+    cipher: Cipher,
+) -> (SecretKey, VaultHeader) {
+    let data_key = SecretKey::default();
+    let kind = if keyfile.is_some() {
+        SlotKind::PasswordAndKeyfile
+    } else {
+        SlotKind::Password
+    };
+    let slot = KeySlot::new(
+        kind,
+        cipher,
+        &KdfParams::default(),
+        password,
+        keyfile,
+        &data_key,
+    )
+    .expect("Couldn't derive key from master password");
+    let header = VaultHeader {
+        slots: vec![slot],
+        ..VaultHeader::new(cipher)
+    };
+    (data_key, header)
+}
+
+/// Generates a recovery key and wraps the data key under it, replacing the
+/// store's previous recovery key if it had one.
+pub fn add_recovery_key(data_key: &SecretKey, header: &mut VaultHeader) -> RecoveryKey {
+    let recovery_key = RecoveryKey::generate().expect("Couldn't generate a recovery key");
+    set_recovery_key(data_key, header, &recovery_key)
+        .expect("Couldn't derive key from the recovery key");
+    recovery_key
+}
+
+fn set_recovery_key(
+    data_key: &SecretKey,
+    header: &mut VaultHeader,
+    recovery_key: &RecoveryKey,
+) -> Result<(), Error> {
+    let slot = KeySlot::new(
+        SlotKind::RecoveryKey,
+        header.cipher,
+        &KdfParams::default(),
+        &recovery_key.secret(),
+        None,
+        data_key,
+    )?;
+    match header
+        .slots
+        .iter()
+        .position(|slot| slot.kind == SlotKind::RecoveryKey)
+    {
+        Some(index) => header.slots[index] = slot,
+        None => header.slots.push(slot),
+    }
+    Ok(())
+}
+
+// Replaces the store's recovery key with a new one that is only ever shown
+// as shares, any `threshold` of which put it back together
+pub fn split_recovery_key(
+    store_path: &str,
+    data_key: &SecretKey,
+    header: &VaultHeader,
+    threshold: u8,
+    count: u8,
+    generations: usize,
+) -> Result<(Vec<RecoveryShare>, Vec<Backup>), Error> {
+    let recovery_key = RecoveryKey::generate()?;
+    let shares = recovery_key.split(threshold, count)?;
+    let mut header = header.clone();
+    set_recovery_key(data_key, &mut header, &recovery_key)?;
+    let sealed = fs::read(store_path)?;
+    let stale = write_header(store_path, &sealed, data_key, &header, generations)?;
+    Ok((shares, stale))
+}
+
+pub fn show_recovery_key(recovery_key: &RecoveryKey) {
+    println!();
+    println!("Your recovery key:");
+    println!();
+    for line in recovery_key.words().chunks(6) {
+        println!("    {}", line.join(" "));
+    }
+    println!();
+    println!("Write it down and keep it somewhere safe. If you forget the master password,");
+    println!("enter it at the master password prompt or run 'arustylock recover' to choose");
+    println!("a new one. Anyone with the recovery key can open the store.");
+    print!("Press Enter when you're done");
+    io::stdout()
+        .flush()
+        .expect("Couldn't write to the terminal");
+    io::stdin()
+        .read_line(&mut String::new())
+        .expect("Couldn't read from the terminal");
+}
+
+// Recovers the data key with the factors at hand. A slot that only needs the
+// keyfile is tried without asking for anything, otherwise the password is
+// asked for until it opens the store, giving up after MAX_UNLOCK_ATTEMPTS
+// wrong guesses.
+fn unlock_sealed(sealed: &[u8], header: &VaultHeader, keyfile: Option<&[u8]>) -> SecretKey {
+    if let Err(e) = check_factors(header, keyfile) {
+        eprintln!("Couldn't open the password store: {}", e);
+        exit(1);
+    }
+    if keyfile.is_some() && header.accepts(false, true) {
+        if let Ok((key, _)) = header.unlock("", keyfile) {
+            if open(&key, sealed).is_ok() {
+                return key;
+            }
+        }
+        if !header.accepts(true, true) {
+            eprintln!("Couldn't open the password store: the keyfile is wrong");
+            exit(1);
+        }
+    }
+
+    for _ in 0..MAX_UNLOCK_ATTEMPTS {
+        let password = prompt_password("Master password: ").expect("Couldn't read master password");
+        // A recovery key typed in place of the password opens the store too
+        let unlocked = match RecoveryKey::parse(&password) {
+            Ok(recovery_key) => header.unlock_with_recovery_key(&recovery_key),
+            Err(_) => header.unlock(&password, keyfile),
+        };
+        if let Ok((key, _)) = unlocked {
+            if open(&key, sealed).is_ok() {
+                return key;
+            }
+        }
+        print_wrong_password(keyfile);
+    }
+    if header.has_recovery_key() {
+        eprintln!("Too many failed attempts, exiting. Run 'arustylock recover' if you forgot the master password");
+    } else {
+        eprintln!("Too many failed attempts, exiting");
+    }
+    exit(1);
+}
+
+pub fn unlock_store(store_path: &str, keyfile: Option<&[u8]>) -> (SecretKey, VaultHeader) {
+    let sealed = fs::read(store_path).expect("Couldn't read the password store");
+    let (header, _) = VaultHeader::parse(&sealed)
+        .unwrap_or_else(|_| fail("The password store is corrupted", store_path));
+    let key = unlock_sealed(&sealed, &header, keyfile);
+    (key, header)
+}
+
+// Re-encrypts a store sealed with the old hardcoded key under a new master
+// password, keeping the original file next to it as a backup. It gets a
+// recovery key like a new store does, shown once the store is written.
+pub fn migrate_legacy_store(
+    store_path: &str,
+    keyfile: Option<&[u8]>,
+    cipher: Cipher,
+) -> (SecretKey, VaultHeader) {
+    let legacy = fs::read(store_path).expect("Couldn't read the password store");
+    let data = open_legacy(&legacy)
+        .unwrap_or_else(|_| fail("The password store is in an unknown format", store_path));
+
+    println!("Your password store was created by an older version of arustylock.");
+    println!("It needs a master password before it can be opened.");
+    let password = choose_master_password();
+    let (secret_key, mut header) = new_store_key(&password, keyfile, cipher);
+    let recovery_key = add_recovery_key(&secret_key, &mut header);
+    let migrated = seal(&secret_key, &header, &data).expect("Couldn't encrypt password store");
+
+    let backup_path = format!("{}.legacy.bak", store_path);
+    let lock = StoreLock::acquire(Path::new(store_path)).expect("Couldn't lock the password store");
+    // Another process may have written the store while the password was
+    // being chosen, and replacing it would lose that
+    if fs::read(store_path).expect("Couldn't read the password store") != legacy {
+        eprintln!(
+            "The password store at {} was changed while it was being upgraded, nothing was written. Start arustylock again",
+            store_path
+        );
+        exit(1);
+    }
+    fs::copy(store_path, &backup_path).expect("Couldn't back up the password store");
+    replace_file(Path::new(store_path), &migrated).expect("Couldn't write the password store");
+    // Not held while the recovery key is shown, which waits for the user
+    drop(lock);
+    println!("The old store was kept at {}", backup_path);
+    show_recovery_key(&recovery_key);
+
+    (secret_key, header)
+}
+
+// Writes the store back with changed key slots, only the header is
+// rewritten. `sealed` was read before asking for passwords, so the store is
+// checked to still hold it before it is replaced. The store is backed up like
+// on any save, then all backups take the new header too: they hold the same
+// data key, so one left with a removed slot would still open every later
+// save. Returns the backups that couldn't take it.
+fn write_header(
+    store_path: &str,
+    sealed: &[u8],
+    data_key: &SecretKey,
+    header: &VaultHeader,
+    generations: usize,
+) -> Result<Vec<Backup>, Error> {
+    let resealed = replace_header(sealed, data_key, header)?;
+    let path = Path::new(store_path);
+    let _lock = StoreLock::acquire(path)?;
+    if fs::read(store_path)? != sealed {
+        return Err(StoreError::Changed.into());
+    }
+    backup::back_up(path, generations)?;
+    replace_file(path, &resealed)?;
+    Ok(backup::reseal(path, data_key, header)?)
+}
+
+// Checks the current password against the store's key slots, then replaces
+// the primary master password slot with one for the new password, with a
+// fresh salt. Other slots, and the entries, are left as they are. A master
+// password that needs a keyfile keeps needing the same one. Returns the
+// backups that couldn't take the new slot.
+pub fn change_master_password(
+    store_path: &str,
+    current: &str,
+    new: &str,
+    keyfile: Option<&[u8]>,
+    generations: usize,
+) -> Result<Vec<Backup>, Error> {
+    let sealed = fs::read(store_path)?;
+    let (mut header, _) = VaultHeader::parse(&sealed)?;
+    check_factors(&header, keyfile)?;
+    let (data_key, _) = header
+        .unlock(current, keyfile)
+        .map_err(|_| wrong_password_error(keyfile))?;
+
+    let kind = match primary_slot(&header) {
+        Some(index) => header.slots[index].kind,
+        None if keyfile.is_some() => SlotKind::PasswordAndKeyfile,
+        None => SlotKind::Password,
+    };
+    if kind.needs_keyfile() && keyfile.is_none() {
+        return Err(Error::KeyfileRequired);
+    }
+    set_primary_slot(&mut header, kind, &data_key, new, keyfile)?;
+
+    write_header(store_path, &sealed, &data_key, &header, generations)
+}
+
+// The slot holding the master password
+fn primary_slot(header: &VaultHeader) -> Option<usize> {
+    header
+        .slots
+        .iter()
+        .position(|slot| matches!(slot.kind, SlotKind::Password | SlotKind::PasswordAndKeyfile))
+}
+
+fn set_primary_slot(
+    header: &mut VaultHeader,
+    kind: SlotKind,
+    data_key: &SecretKey,
+    password: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<(), Error> {
+    let slot_keyfile = if kind.needs_keyfile() { keyfile } else { None };
+    let slot = KeySlot::new(
+        kind,
+        header.cipher,
+        &KdfParams::default(),
+        password,
+        slot_keyfile,
+        data_key,
+    )?;
+    match primary_slot(header) {
+        Some(index) => header.slots[index] = slot,
+        None => header.slots.insert(0, slot),
+    }
+    Ok(())
+}
+
+// Asks for the recovery key, or for enough shares of it, until it opens the
+// store
+fn unlock_with_recovery(sealed: &[u8], header: &VaultHeader) -> SecretKey {
+    let mut shares: Vec<RecoveryShare> = Vec::new();
+    let mut failures = 0;
+    while failures < MAX_UNLOCK_ATTEMPTS {
+        let prompt = match shares.first() {
+            Some(share) => format!("Share {} of {}: ", shares.len() + 1, share.threshold),
+            None => String::from("Recovery key or share: "),
+        };
+        let phrase = prompt_password(&prompt).expect("Couldn't read the recovery key");
+        let recovery_key = match RecoveryPhrase::parse(&phrase) {
+            Ok(RecoveryPhrase::Key(recovery_key)) if shares.is_empty() => recovery_key,
+            Ok(RecoveryPhrase::Key(_)) => {
+                println!("That's a whole recovery key, enter the next share");
+                continue;
+            }
+            Ok(RecoveryPhrase::Share(share)) => {
+                if shares.iter().any(|other| other.share.x == share.share.x) {
+                    println!("That share was already entered");
+                    continue;
+                }
+                shares.push(share);
+                if shares.len() < shares[0].threshold as usize {
+                    continue;
+                }
+                match RecoveryKey::combine(&shares) {
+                    Ok(recovery_key) => recovery_key,
+                    Err(e) => {
+                        println!("Couldn't combine the shares: {}", e);
+                        shares.clear();
+                        failures += 1;
+                        continue;
+                    }
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+                failures += 1;
+                continue;
+            }
+        };
+        match header.unlock_with_recovery_key(&recovery_key) {
+            Ok((key, _)) if open(&key, sealed).is_ok() => return key,
+            _ => println!("That recovery key doesn't open the password store"),
+        }
+        shares.clear();
+        failures += 1;
+    }
+    eprintln!("Too many failed attempts, exiting");
+    exit(1);
+}
+
+// Opens the store with its recovery key and sets a new master password. The
+// keyfile isn't needed to recover; the new master password needs one only if
+// --keyfile is given.
+pub fn run_recover(store_path: &str, keyfile: Option<&[u8]>, generations: usize) {
+    let sealed = fs::read(store_path).expect("Couldn't read the password store");
+    let (mut header, _) = VaultHeader::parse(&sealed)
+        .unwrap_or_else(|_| fail("The password store is corrupted", store_path));
+    if !header.has_recovery_key() {
+        eprintln!("The password store has no recovery key");
+        exit(1);
+    }
+
+    let data_key = unlock_with_recovery(&sealed, &header);
+    let new = choose_master_password();
+    let kind = if keyfile.is_some() {
+        SlotKind::PasswordAndKeyfile
+    } else {
+        SlotKind::Password
+    };
+    let result = set_primary_slot(&mut header, kind, &data_key, &new, keyfile)
+        .and_then(|_| write_header(store_path, &sealed, &data_key, &header, generations));
+    match result {
+        Ok(stale) => {
+            println!("Master password reset");
+            warn_stale_backups(&stale);
+        }
+        Err(e) => {
+            eprintln!("Couldn't reset the master password: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub fn run_change_password(store_path: &str, keyfile: Option<&[u8]>, generations: usize) {
+    let current =
+        prompt_password("Current master password: ").expect("Couldn't read master password");
+    let new = choose_master_password();
+    match change_master_password(store_path, &current, &new, keyfile, generations) {
+        Ok(stale) => {
+            println!("Master password changed");
+            warn_stale_backups(&stale);
+        }
+        Err(e) => {
+            eprintln!("Couldn't change the master password: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub fn run_list_key_slots(store_path: &str) {
+    let sealed = fs::read(store_path).expect("Couldn't read the password store");
+    let (header, _) = VaultHeader::parse(&sealed)
+        .unwrap_or_else(|_| fail("The password store is corrupted", store_path));
+    println!("Cipher: {}", header.cipher.name());
+    for (index, slot) in header.slots.iter().enumerate() {
+        println!("{}: {}", index, slot.kind.name());
+    }
+}
+
+pub fn run_split_recovery_key(
+    store_path: &str,
+    keyfile: Option<&[u8]>,
+    threshold: u8,
+    count: u8,
+    generations: usize,
+) {
+    let (data_key, header) = unlock_store(store_path, keyfile);
+    match split_recovery_key(
+        store_path,
+        &data_key,
+        &header,
+        threshold,
+        count,
+        generations,
+    ) {
+        Ok((shares, stale)) => {
+            println!();
+            println!(
+                "The recovery key was replaced by one split into {} shares, any {} of which",
+                count, threshold
+            );
+            println!(
+                "open the store with 'arustylock recover'. Hand each share to a different person:"
+            );
+            for share in shares {
+                println!();
+                println!("    Share {}: {}", share.share.x, share.words().join(" "));
+            }
+            warn_stale_backups(&stale);
+        }
+        Err(e) => {
+            eprintln!("Couldn't split the recovery key: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub fn run_new_recovery_key(store_path: &str, keyfile: Option<&[u8]>, generations: usize) {
+    let (data_key, mut header) = unlock_store(store_path, keyfile);
+    let recovery_key = add_recovery_key(&data_key, &mut header);
+    let sealed = fs::read(store_path).expect("Couldn't read the password store");
+    match write_header(store_path, &sealed, &data_key, &header, generations) {
+        Ok(stale) => warn_stale_backups(&stale),
+        Err(e) => {
+            eprintln!("Couldn't add the recovery key: {}", e);
+            exit(1);
+        }
+    }
+    show_recovery_key(&recovery_key);
+}
+
+pub fn run_add_key_slot(
+    store_path: &str,
+    keyfile: Option<&[u8]>,
+    new_slot: &NewSlot,
+    generations: usize,
+) {
+    let (data_key, mut header) = unlock_store(store_path, keyfile);
+    let slot = match new_slot {
+        NewSlot::Password => {
+            let password = choose_secret("password for the new slot");
+            KeySlot::new(
+                SlotKind::Password,
+                header.cipher,
+                &KdfParams::default(),
+                &password,
+                None,
+                &data_key,
+            )
+        }
+        NewSlot::Recovery => {
+            let passphrase = choose_secret("recovery passphrase");
+            KeySlot::new(
+                SlotKind::Recovery,
+                header.cipher,
+                &KdfParams::default(),
+                &passphrase,
+                None,
+                &data_key,
+            )
+        }
+        NewSlot::Keyfile(path) => KeySlot::new(
+            SlotKind::Keyfile,
+            header.cipher,
+            &KdfParams::default(),
+            "",
+            Some(&read_keyfile(path)),
+            &data_key,
+        ),
+    }
+    .expect("Couldn't create the key slot");
+    header.slots.push(slot);
+
+    let sealed = fs::read(store_path).expect("Couldn't read the password store");
+    match write_header(store_path, &sealed, &data_key, &header, generations) {
+        Ok(stale) => {
+            println!("Added key slot {}", header.slots.len() - 1);
+            warn_stale_backups(&stale);
+        }
+        Err(e) => {
+            eprintln!("Couldn't add the key slot: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub fn run_remove_key_slot(
+    store_path: &str,
+    keyfile: Option<&[u8]>,
+    index: usize,
+    generations: usize,
+) {
+    let (data_key, mut header) = unlock_store(store_path, keyfile);
+    let result = if index >= header.slots.len() {
+        Err(Error::NoSuchKeySlot(index))
+    } else if header.slots.len() == 1 {
+        Err(Error::LastKeySlot)
+    } else {
+        header.slots.remove(index);
+        let sealed = fs::read(store_path).expect("Couldn't read the password store");
+        write_header(store_path, &sealed, &data_key, &header, generations)
+    };
+    match result {
+        Ok(stale) => {
+            println!("Removed key slot {}", index);
+            warn_stale_backups(&stale);
+        }
+        Err(e) => {
+            eprintln!("Couldn't remove the key slot: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub fn run_list_vaults(config_dir: &str) {
+    let names = stores::list(Path::new(config_dir)).expect("Couldn't list the password stores");
+    if names.is_empty() {
+        println!("There are no password stores yet");
+    }
+    for name in names {
+        println!("{}", name);
+    }
+}
+
+pub fn run_list_backups(store_path: &str) {
+    let backups = backup::list(Path::new(store_path)).expect("Couldn't list the backups");
+    if backups.is_empty() {
+        println!("There are no backups of the password store");
+    }
+    for (index, backup) in backups.iter().enumerate() {
+        println!(
+            "{}: {}",
+            index,
+            backup
+                .created
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+}
+
+// A backup opens with the key slots the store had when the backup was last
+// sealed, which are the current ones unless a change to them failed half
+// way. It is unlocked before it replaces the store, so the store is never
+// swapped for one that can't be opened.
+pub fn run_restore_backup(
+    store_path: &str,
+    keyfile: Option<&[u8]>,
+    index: usize,
+    generations: usize,
+) {
+    let backups = backup::list(Path::new(store_path)).expect("Couldn't list the backups");
+    let chosen = match backups.get(index) {
+        Some(chosen) => chosen,
+        None => {
+            eprintln!(
+                "Couldn't restore the backup: {}",
+                Error::NoSuchBackup(index)
+            );
+            exit(1);
+        }
+    };
+    println!("Unlock the backup to restore it");
+    unlock_store(&chosen.path.to_string_lossy(), keyfile);
+    match backup::restore(Path::new(store_path), chosen, generations) {
+        Ok(()) => println!(
+            "Restored the backup from {}",
+            chosen
+                .created
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        ),
+        Err(e) => {
+            eprintln!("Couldn't restore the backup: {}", e);
+            exit(1);
+        }
+    }
+}
+
+// Prints where each entry is, sorted by path, and nothing secret. With a
+// folder, only the entries in it and the folders below it.
+pub fn run_list_entries(store_path: &str, keyfile: Option<&[u8]>, folder: Option<&str>) {
+    let (key, _) = unlock_store(store_path, keyfile);
+    let vault = crate::open_vault(store_path, &key).unwrap_or_else(|e| {
+        eprintln!("Couldn't read the password store: {}", e);
+        exit(1);
+    });
+    let folder = folder.map(folder_path).unwrap_or_default();
+    let below = format!("{}/", folder);
+    let mut entries: Vec<(String, &str)> = vault
+        .list()
+        .iter()
+        .filter(|entry| {
+            folder.is_empty() || entry.folder == folder || entry.folder.starts_with(&below)
+        })
+        .map(|entry| (entry.path(), entry.item.name()))
+        .collect();
+    if entries.is_empty() {
+        println!(
+            "There are no entries in {}",
+            if folder.is_empty() {
+                "the password store"
+            } else {
+                &folder
+            }
+        );
+    }
+    entries.sort();
+    for (path, kind) in entries {
+        println!("{} ({})", path, kind);
+    }
+}
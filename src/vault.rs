@@ -0,0 +1,687 @@
+use crate::atomic::replace_file_with;
+use crate::backup::{self, DEFAULT_GENERATIONS};
+use crate::encryption::encryption::{decrypt_data, read_header, write_encrypted};
+use crate::encryption::error::VaultError;
+use crate::encryption::header::VaultHeader;
+use crate::item::{Item, Login};
+use crate::lock::{Fingerprint, StoreLock};
+use chrono::{DateTime, Utc};
+use orion::aead::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+// A decrypted password store. The store is a JSON list of passwords, sealed
+// as described in `encryption`. A `Vault` holds the whole list in memory;
+// changes only reach the file on `save`, and `is_dirty` tells whether there
+// are any that haven't yet. Every save keeps the file it replaces as a
+// backup, see `backup`.
+//
+// Entries can be put in folders, named by paths like `work/aws/prod`.
+// Folders only exist through the entries in them.
+//
+// Changing an entry's login keeps the old one in the entry's history, newest
+// first, up to a set depth, so it can be looked up or put back.
+//
+// Saving fails with `StoreError::Changed` if someone else wrote the file
+// since the vault read it, see `lock`. The vault can then `reload` the file,
+// dropping its own changes, or `merge` them into it and save again.
+
+/// Why a change to a vault couldn't be made: the store couldn't be read or
+/// written, or the entry it was for isn't there.
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error(transparent)]
+    Vault(#[from] VaultError),
+    #[error("there is no entry {0}")]
+    NoSuchEntry(EntryId),
+    #[error("entry {0} has no previous login {1}")]
+    NoSuchLogin(EntryId, usize),
+    #[error("the password store was changed by another process")]
+    Changed,
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Vault(e.into())
+    }
+}
+
+/// Identifies an entry for as long as it exists, wherever it moves in the
+/// list. IDs are random, so entries added by different processes don't
+/// collide.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct EntryId(String);
+
+impl EntryId {
+    /// A new random ID.
+    pub fn generate() -> EntryId {
+        let mut bytes = [0u8; 16];
+        orion::util::secure_rand_bytes(&mut bytes).expect("Couldn't generate an entry ID");
+        EntryId(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for EntryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Anything else an entry holds, like an account number or the answer to a
+/// security question.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    /// Whether the value is a secret, kept out of sight until asked for.
+    #[serde(default)]
+    pub concealed: bool,
+}
+
+impl CustomField {
+    pub fn new<S: Into<String>>(name: S, value: S, concealed: bool) -> CustomField {
+        CustomField {
+            name: name.into(),
+            value: value.into(),
+            concealed,
+        }
+    }
+}
+
+impl Zeroize for CustomField {
+    fn zeroize(&mut self) {
+        self.name.zeroize();
+        self.value.zeroize();
+    }
+}
+
+impl Drop for CustomField {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// How many previous logins an entry keeps unless told otherwise.
+pub const DEFAULT_HISTORY_DEPTH: usize = 5;
+
+/// A login an entry had before it was changed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreviousLogin {
+    pub username: String,
+    pub password: String,
+    /// When it stopped being the entry's login.
+    pub replaced: DateTime<Utc>,
+}
+
+impl Zeroize for PreviousLogin {
+    fn zeroize(&mut self) {
+        self.username.zeroize();
+        self.password.zeroize();
+    }
+}
+
+impl Drop for PreviousLogin {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// One entry in the store. Everything but the domain was added later and may
+/// be missing from older stores, so it all has a default.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(from = "StoredPassword")]
+pub struct Password {
+    /// Set by `Vault::insert`, and given to entries from older stores when
+    /// they are read.
+    pub id: EntryId,
+    pub domain: String,
+    /// The folder the entry is in, empty for the top level. Kept as
+    /// `folder_path` makes it.
+    pub folder: String,
+    /// What kind of entry it is, with its fields. A login's username,
+    /// password and URLs are in here.
+    pub item: Item,
+    pub notes: String,
+    pub tags: Vec<String>,
+    pub fields: Vec<CustomField>,
+    /// When the entry was added, `None` if that was before times were kept.
+    pub created: Option<DateTime<Utc>>,
+    /// When the entry was last changed, `None` if that was before times were
+    /// kept.
+    pub modified: Option<DateTime<Utc>>,
+    /// Logins the entry had before, newest first. Kept by `Vault::update`.
+    pub history: Vec<PreviousLogin>,
+}
+
+// An entry as read from the store. Stores from before there were other kinds
+// than logins keep the login's username, password and URLs on the entry
+// itself, and have no item or one without them.
+#[derive(Deserialize)]
+struct StoredPassword {
+    #[serde(default)]
+    id: EntryId,
+    domain: String,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    urls: Option<Vec<String>>,
+    #[serde(default)]
+    folder: String,
+    #[serde(default)]
+    item: Item,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    fields: Vec<CustomField>,
+    #[serde(default)]
+    created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    history: Vec<PreviousLogin>,
+}
+
+impl From<StoredPassword> for Password {
+    fn from(mut stored: StoredPassword) -> Password {
+        if let Item::Login(login) = &mut stored.item {
+            if let Some(username) = stored.username.take() {
+                login.username = username;
+            }
+            if let Some(password) = stored.password.take() {
+                login.password = password;
+            }
+            if let Some(urls) = stored.urls.take() {
+                login.urls = urls;
+            }
+        }
+        stored.username.zeroize();
+        stored.password.zeroize();
+        Password {
+            id: stored.id,
+            domain: stored.domain,
+            folder: stored.folder,
+            item: stored.item,
+            notes: stored.notes,
+            tags: stored.tags,
+            fields: stored.fields,
+            created: stored.created,
+            modified: stored.modified,
+            history: stored.history,
+        }
+    }
+}
+
+impl Password {
+    /// A login entry, the other fields are left empty.
+    pub fn new<S: Into<String>>(domain: S, username: S, password: S) -> Password {
+        Password::new_item(domain, Item::Login(Login::new(username, password)))
+    }
+
+    /// An entry holding `item`, called `name`.
+    pub fn new_item<S: Into<String>>(name: S, item: Item) -> Password {
+        Password {
+            id: EntryId::default(),
+            domain: name.into(),
+            folder: String::new(),
+            item,
+            notes: String::new(),
+            tags: Vec::new(),
+            fields: Vec::new(),
+            created: None,
+            modified: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// Where the entry is, its folder and name, like `work/aws/console`.
+    pub fn path(&self) -> String {
+        if self.folder.is_empty() {
+            self.domain.clone()
+        } else {
+            format!("{}/{}", self.folder, self.domain)
+        }
+    }
+
+    /// The entry's login, `None` if it holds another kind of item.
+    pub fn login(&self) -> Option<&Login> {
+        match &self.item {
+            Item::Login(login) => Some(login),
+            _ => None,
+        }
+    }
+
+    pub fn login_mut(&mut self) -> Option<&mut Login> {
+        match &mut self.item {
+            Item::Login(login) => Some(login),
+            _ => None,
+        }
+    }
+}
+
+impl Zeroize for Password {
+    fn zeroize(&mut self) {
+        self.domain.zeroize();
+        self.folder.zeroize();
+        self.item.zeroize();
+        self.notes.zeroize();
+        self.tags.zeroize();
+        self.fields.zeroize();
+        self.history.zeroize();
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+pub struct Vault {
+    path: PathBuf,
+    key: SecretKey,
+    header: VaultHeader,
+    passwords: Vec<Password>,
+    // The entries as they were last read or saved, for `merge`
+    base: Vec<Password>,
+    fingerprint: Option<Fingerprint>,
+    dirty: bool,
+    backups: usize,
+    history_depth: usize,
+}
+
+impl Vault {
+    /// Opens the store at `path` with its master password, and the keyfile
+    /// if it was set up with one. A keyfile is ignored if no key slot takes
+    /// one, so the same one can be passed for every store.
+    pub fn unlock<P: AsRef<Path>>(
+        path: P,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<Vault, VaultError> {
+        let header = read_header(&mut File::open(&path)?)?;
+        let keyfile = keyfile.filter(|_| header.uses_keyfile());
+        let (key, _) = header
+            .unlock(password, keyfile)
+            .map_err(|_| VaultError::WrongKey)?;
+        Vault::open(path, &key)
+    }
+
+    /// Opens the store at `path` with the data key it is encrypted under.
+    pub fn open<P: AsRef<Path>>(path: P, key: &SecretKey) -> Result<Vault, VaultError> {
+        let (header, passwords, fingerprint) = read_store(path.as_ref(), key)?;
+        Vault::new(path.as_ref(), key, header, passwords, fingerprint)
+    }
+
+    /// Builds a vault from an already decrypted store, for callers that
+    /// handle the plaintext themselves. `fingerprint` is the file's from
+    /// `Fingerprint::of`, taken before `plaintext` was read from it, so a
+    /// write landing in between is taken for a change instead of being
+    /// overwritten by the next save.
+    pub fn from_plaintext<P: AsRef<Path>>(
+        path: P,
+        key: &SecretKey,
+        header: VaultHeader,
+        fingerprint: Option<Fingerprint>,
+        plaintext: &[u8],
+    ) -> Result<Vault, VaultError> {
+        let passwords = serde_json::from_slice(plaintext)?;
+        Vault::new(path.as_ref(), key, header, passwords, fingerprint)
+    }
+
+    fn new(
+        path: &Path,
+        key: &SecretKey,
+        header: VaultHeader,
+        mut passwords: Vec<Password>,
+        fingerprint: Option<Fingerprint>,
+    ) -> Result<Vault, VaultError> {
+        let assigned = assign_ids(&mut passwords);
+        Ok(Vault {
+            path: path.to_path_buf(),
+            key: SecretKey::from_slice(key.unprotected_as_bytes())
+                .map_err(|_| VaultError::WrongKey)?,
+            header,
+            base: passwords.clone(),
+            passwords,
+            fingerprint,
+            // New IDs have to be saved to stay the same
+            dirty: assigned,
+            backups: DEFAULT_GENERATIONS,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+        })
+    }
+
+    /// The store as it would be encrypted by `save`. The buffer is sized up
+    /// front, so it is never grown by copying and no stray copies of the
+    /// plaintext are left in freed memory.
+    pub fn to_plaintext(&self) -> Result<Zeroizing<Vec<u8>>, VaultError> {
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, &self.passwords)?;
+        let mut json = Zeroizing::new(Vec::with_capacity(counter.0));
+        serde_json::to_writer(&mut *json, &self.passwords)?;
+        Ok(json)
+    }
+
+    /// Encrypts the store and writes it back to its file.
+    pub fn save(&mut self) -> Result<(), StoreError> {
+        let plaintext = self.to_plaintext()?;
+        self.write_plaintext(&plaintext)
+    }
+
+    /// Encrypts `plaintext`, as returned by `to_plaintext`, and writes it to
+    /// the vault's file, for callers that handle the plaintext themselves.
+    /// The file is replaced atomically, see `atomic`.
+    pub fn write_plaintext(&mut self, plaintext: &[u8]) -> Result<(), StoreError> {
+        let _lock = StoreLock::acquire(&self.path)?;
+        if !self.unchanged_on_disk()? {
+            return Err(StoreError::Changed);
+        }
+        backup::back_up(&self.path, self.backups)?;
+        replace_file_with(&self.path, |file| {
+            write_encrypted(file, &self.key, &self.header, plaintext)
+        })?;
+        self.fingerprint = Fingerprint::of(&self.path)?;
+        self.base = self.passwords.clone();
+        self.dirty = false;
+        Ok(())
+    }
+
+    // Compares the whole file, since a save right after another can leave the
+    // size and modification time as they were
+    fn unchanged_on_disk(&self) -> io::Result<bool> {
+        match fs::read(&self.path) {
+            Ok(contents) => Ok(self
+                .fingerprint
+                .as_ref()
+                .is_some_and(|fingerprint| fingerprint.matches(&contents))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(self.fingerprint.is_none()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether someone else wrote the file since the vault read or saved it.
+    /// Cheap enough to poll, but may miss a change made within the
+    /// resolution of file times; `save` never does.
+    pub fn changed_on_disk(&self) -> Result<bool, VaultError> {
+        match &self.fingerprint {
+            Some(fingerprint) => Ok(!fingerprint.still_matches(&self.path)?),
+            None => Ok(self.path.exists()),
+        }
+    }
+
+    /// Reads the file again, dropping any unsaved changes.
+    pub fn reload(&mut self) -> Result<(), VaultError> {
+        let (header, mut passwords, fingerprint) = read_store(&self.path, &self.key)?;
+        self.dirty = assign_ids(&mut passwords);
+        self.header = header;
+        self.base = passwords.clone();
+        self.passwords = passwords;
+        self.fingerprint = fingerprint;
+        Ok(())
+    }
+
+    /// Reads the file again and makes the unsaved changes to what it holds
+    /// now, matching entries up by ID. Entries deleted here are deleted there
+    /// too, unless they were changed there. Entries added or changed here
+    /// are added or take the place of the entry there, even one changed or
+    /// deleted there, so nothing is lost that wasn't deleted on purpose. The
+    /// result still has to be saved.
+    pub fn merge(&mut self) -> Result<(), VaultError> {
+        let (header, mut theirs, fingerprint) = read_store(&self.path, &self.key)?;
+        let assigned = assign_ids(&mut theirs);
+        let mut merged = theirs.clone();
+        for old in &self.base {
+            if self.position(&old.id).is_none() {
+                if let Some(index) = merged.iter().position(|entry| entry == old) {
+                    merged.remove(index);
+                }
+            }
+        }
+        for entry in &self.passwords {
+            if self.base.contains(entry) {
+                continue;
+            }
+            match merged.iter().position(|other| other.id == entry.id) {
+                Some(index) => merged[index] = entry.clone(),
+                None => merged.push(entry.clone()),
+            }
+        }
+        self.dirty = assigned || merged != theirs;
+        self.header = header;
+        self.base = theirs;
+        self.passwords = merged;
+        self.fingerprint = fingerprint;
+        Ok(())
+    }
+
+    /// Whether there are changes that haven't been saved.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Sets how many backups of the file saves keep, 0 for none. Defaults to
+    /// `backup::DEFAULT_GENERATIONS`.
+    pub fn set_backups(&mut self, generations: usize) {
+        self.backups = generations;
+    }
+
+    pub fn backups(&self) -> usize {
+        self.backups
+    }
+
+    pub fn history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    /// Sets how many previous logins `update` keeps per entry, 0 for none.
+    /// Entries with more lose the oldest ones the next time they change.
+    /// Defaults to `DEFAULT_HISTORY_DEPTH`.
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history_depth = depth;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn key(&self) -> &SecretKey {
+        &self.key
+    }
+
+    pub fn header(&self) -> &VaultHeader {
+        &self.header
+    }
+
+    pub fn list(&self) -> &[Password] {
+        &self.passwords
+    }
+
+    pub fn get(&self, id: &EntryId) -> Option<&Password> {
+        self.passwords.iter().find(|entry| &entry.id == id)
+    }
+
+    /// Where the entry is in `list`.
+    pub fn position(&self, id: &EntryId) -> Option<usize> {
+        self.passwords.iter().position(|entry| &entry.id == id)
+    }
+
+    /// Adds an entry at the end of the list, returning its ID. It gets a new
+    /// ID unless it has one no other entry has. Its creation time is set to
+    /// now unless it already has one, and so is its modification time.
+    pub fn insert(&mut self, mut password: Password) -> EntryId {
+        if password.id.is_empty() || self.position(&password.id).is_some() {
+            password.id = EntryId::generate();
+        }
+        password.folder = folder_path(&password.folder);
+        let created = *password.created.get_or_insert_with(Utc::now);
+        password.modified.get_or_insert(created);
+        let id = password.id.clone();
+        self.passwords.push(password);
+        self.dirty = true;
+        id
+    }
+
+    /// Replaces the entry with ID `id`, returning the old one. The new entry
+    /// takes over the ID and history, keeps the old one's creation time
+    /// unless it has its own, and is marked as modified now. If the username
+    /// or password changed, the old login goes into the history.
+    pub fn update(&mut self, id: &EntryId, mut password: Password) -> Result<Password, StoreError> {
+        let index = self
+            .position(id)
+            .ok_or_else(|| StoreError::NoSuchEntry(id.clone()))?;
+        let entry = &mut self.passwords[index];
+        password.id = id.clone();
+        password.folder = folder_path(&password.folder);
+        if password.created.is_none() {
+            password.created = entry.created;
+        }
+        let now = Utc::now();
+        password.modified = Some(now);
+        password.history = entry.history.clone();
+        if let (Item::Login(old), Item::Login(new)) = (&entry.item, &password.item) {
+            if new.username != old.username || new.password != old.password {
+                password.history.insert(
+                    0,
+                    PreviousLogin {
+                        username: old.username.clone(),
+                        password: old.password.clone(),
+                        replaced: now,
+                    },
+                );
+            }
+        }
+        password.history.truncate(self.history_depth);
+        self.dirty = true;
+        Ok(mem::replace(entry, password))
+    }
+
+    /// Makes previous login `index` of the entry with ID `id` its login
+    /// again. The login it replaces goes into the history in its place.
+    pub fn restore_login(&mut self, id: &EntryId, index: usize) -> Result<(), StoreError> {
+        let position = self
+            .position(id)
+            .ok_or_else(|| StoreError::NoSuchEntry(id.clone()))?;
+        let entry = &mut self.passwords[position];
+        let login = match &mut entry.item {
+            Item::Login(login) if index < entry.history.len() => login,
+            _ => return Err(StoreError::NoSuchLogin(id.clone(), index)),
+        };
+        let mut previous = entry.history.remove(index);
+        let now = Utc::now();
+        let replaced = PreviousLogin {
+            username: mem::replace(&mut login.username, mem::take(&mut previous.username)),
+            password: mem::replace(&mut login.password, mem::take(&mut previous.password)),
+            replaced: now,
+        };
+        entry.history.insert(0, replaced);
+        entry.history.truncate(self.history_depth);
+        entry.modified = Some(now);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Puts the entry with ID `id` in `folder`, the top level if it's empty.
+    pub fn move_to(&mut self, id: &EntryId, folder: &str) -> Result<(), StoreError> {
+        let index = self
+            .position(id)
+            .ok_or_else(|| StoreError::NoSuchEntry(id.clone()))?;
+        let folder = folder_path(folder);
+        let entry = &mut self.passwords[index];
+        if entry.folder != folder {
+            entry.folder = folder;
+            entry.modified = Some(Utc::now());
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Every folder that has entries in it or in folders below it, sorted.
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders = BTreeSet::new();
+        for entry in &self.passwords {
+            let mut folder = entry.folder.as_str();
+            while !folder.is_empty() && folders.insert(folder.to_string()) {
+                folder = folder.rsplit_once('/').map_or("", |(parent, _)| parent);
+            }
+        }
+        folders.into_iter().collect()
+    }
+
+    /// Removes the entry with ID `id`, returning it.
+    pub fn delete(&mut self, id: &EntryId) -> Result<Password, StoreError> {
+        let index = self
+            .position(id)
+            .ok_or_else(|| StoreError::NoSuchEntry(id.clone()))?;
+        self.dirty = true;
+        Ok(self.passwords.remove(index))
+    }
+}
+
+// The fingerprint is taken first, so a file replaced while it is read is
+// only ever mistaken for a changed one
+fn read_store(
+    path: &Path,
+    key: &SecretKey,
+) -> Result<(VaultHeader, Vec<Password>, Option<Fingerprint>), VaultError> {
+    let fingerprint = Fingerprint::of(path)?;
+    let mut file = File::open(path)?;
+    let header = read_header(&mut file)?;
+    let plaintext = decrypt_data(&mut file, key)?;
+    Ok((header, serde_json::from_slice(&plaintext)?, fingerprint))
+}
+
+/// `folder` the way entries keep it: without empty names, surrounding
+/// spaces or slashes at either end, so `/work//aws/ ` is `work/aws`.
+pub fn folder_path(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Gives a new ID to entries without one, from stores written before there
+// were IDs, and to any that share one. Returns whether any were given.
+fn assign_ids(entries: &mut [Password]) -> bool {
+    let mut seen = HashSet::new();
+    let mut assigned = false;
+    for entry in entries {
+        if entry.id.is_empty() || !seen.insert(entry.id.clone()) {
+            entry.id = EntryId::generate();
+            seen.insert(entry.id.clone());
+            assigned = true;
+        }
+    }
+    assigned
+}
+
+// Counts the bytes written to it without keeping them
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+// Fixtures shared by the integration tests. Every test file is its own crate
+// and uses only some of them.
+#![allow(dead_code)]
+
+use arustylock::encryption::encryption::seal;
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::keyslot::{KdfParams, KeySlot, SlotKind};
+use arustylock::vault::Password;
+use orion::aead::SecretKey;
+use std::fs;
+use std::path::PathBuf;
+
+pub const SAMPLE_FILE_PATHS: [&str; 2] = ["tests/sample1.json", "tests/sample2.json"];
+
+// Cheap KDF settings so the tests don't spend their time in Argon2
+pub const TEST_PARAMS: KdfParams = KdfParams {
+    iterations: 3,
+    memory_kib: 8,
+};
+
+// A path in the temp dir that other test runs don't share
+pub fn test_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("arustylock-{}-{}", std::process::id(), name))
+}
+
+// A new data key and a header with a single slot for `password`
+pub fn test_vault(cipher: Cipher, password: &str) -> (SecretKey, VaultHeader) {
+    let data_key = SecretKey::default();
+    let slot = KeySlot::new(
+        SlotKind::Password,
+        cipher,
+        &TEST_PARAMS,
+        password,
+        None,
+        &data_key,
+    )
+    .unwrap();
+    let header = VaultHeader {
+        slots: vec![slot],
+        ..VaultHeader::new(cipher)
+    };
+    (data_key, header)
+}
+
+// Writes a store holding the first sample to the temp dir, opened with the
+// password "correct horse"
+pub fn test_store(name: &str) -> PathBuf {
+    let (data_key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let plaintext = fs::read(SAMPLE_FILE_PATHS[0]).unwrap();
+    let path = test_path(name);
+    fs::write(&path, seal(&data_key, &header, &plaintext).unwrap()).unwrap();
+    path
+}
+
+pub fn entry(domain: &str) -> Password {
+    Password::new(domain, "user", "hunter2")
+}
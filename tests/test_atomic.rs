@@ -0,0 +1,52 @@
+use arustylock::atomic::{replace_file, replace_file_with};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+mod common;
+
+use common::test_path;
+
+fn tmp_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.tmp", path.display()))
+}
+
+#[test]
+fn test_replace_file_swaps_contents() {
+    let path = test_path("atomic-replace");
+    replace_file(&path, b"first").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    replace_file(&path, b"second").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+    assert!(!tmp_path(&path).exists());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_failed_write_leaves_the_file_untouched() {
+    let path = test_path("atomic-failure");
+    replace_file(&path, b"credentials").unwrap();
+
+    let result: io::Result<()> = replace_file_with(&path, |file| {
+        file.write_all(b"half of the new")?;
+        Err(io::Error::other("disk full"))
+    });
+    assert!(result.is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"credentials");
+    assert!(!tmp_path(&path).exists());
+    fs::remove_file(path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_replace_file_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = test_path("atomic-permissions");
+    replace_file(&path, b"first").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    replace_file(&path, b"second").unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    fs::remove_file(path).unwrap();
+}
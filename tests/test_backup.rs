@@ -0,0 +1,124 @@
+use arustylock::backup::{self, Backup};
+use arustylock::encryption::error::VaultError;
+use arustylock::encryption::header::VaultHeader;
+use arustylock::encryption::keyslot::{KeySlot, SlotKind};
+use arustylock::vault::{Password, Vault};
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+use common::{test_path, test_store, TEST_PARAMS};
+
+#[test]
+fn test_backups_rotate() {
+    let path = test_path("backup-rotate");
+    for generation in 0..4 {
+        fs::write(&path, format!("generation {}", generation)).unwrap();
+        let made = backup::back_up(&path, 2).unwrap().unwrap();
+        assert_eq!(
+            fs::read(&made.path).unwrap(),
+            format!("generation {}", generation).as_bytes()
+        );
+    }
+
+    let backups = backup::list(&path).unwrap();
+    assert_eq!(backups.len(), 2);
+    assert!(backups[0].created > backups[1].created);
+    assert_eq!(fs::read(&backups[0].path).unwrap(), b"generation 3");
+    assert_eq!(fs::read(&backups[1].path).unwrap(), b"generation 2");
+
+    backup::prune(&path, 0).unwrap();
+    assert!(backup::list(&path).unwrap().is_empty());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_backups_can_be_turned_off() {
+    let path = test_path("backup-off");
+    assert_eq!(backup::back_up(&path, 1).unwrap(), None);
+    fs::write(&path, b"store").unwrap();
+    assert_eq!(backup::back_up(&path, 0).unwrap(), None);
+
+    // Other files next to the store aren't taken for backups
+    let legacy = PathBuf::from(format!("{}.legacy.bak", path.display()));
+    fs::write(&legacy, b"legacy store").unwrap();
+    assert_eq!(backup::list(&path).unwrap(), Vec::<Backup>::new());
+    fs::remove_file(legacy).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_save_backs_up_and_restore_undoes_it() {
+    let path = test_store("backup-restore");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let count = vault.list().len();
+    let first = vault.list()[0].id.clone();
+    vault.delete(&first).unwrap();
+    vault.insert(Password::new("example.com", "user", "hunter2"));
+    vault.save().unwrap();
+
+    let backups = backup::list(&path).unwrap();
+    assert_eq!(backups.len(), 1);
+    let before = Vault::open(&backups[0].path, vault.key()).unwrap();
+    assert_eq!(before.list().len(), count);
+
+    let backed_up = fs::read(&backups[0].path).unwrap();
+    backup::restore(&path, &backups[0], 5).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), backed_up);
+    // The store that was replaced is kept as well
+    let backups = backup::list(&path).unwrap();
+    assert_eq!(backups.len(), 2);
+    let replaced = Vault::open(&backups[0].path, vault.key()).unwrap();
+    assert_eq!(replaced.list(), vault.list());
+
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_backups_take_new_key_slots() {
+    let path = test_store("backup-reseal");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    vault.insert(Password::new("example.com", "user", "hunter2"));
+    vault.save().unwrap();
+    vault.insert(Password::new("example.net", "user", "hunter2"));
+    vault.save().unwrap();
+    let stale = backup::list(&path).unwrap()[1].path.clone();
+    fs::write(&stale, b"not a store").unwrap();
+
+    let slot = KeySlot::new(
+        SlotKind::Password,
+        vault.header().cipher,
+        &TEST_PARAMS,
+        "battery staple",
+        None,
+        vault.key(),
+    )
+    .unwrap();
+    let header = VaultHeader {
+        slots: vec![slot],
+        ..vault.header().clone()
+    };
+    let failed = backup::reseal(&path, vault.key(), &header).unwrap();
+
+    // The backup the key doesn't open is reported and left alone, the other
+    // one only opens with the new slot
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].path, stale);
+    assert_eq!(fs::read(&stale).unwrap(), b"not a store");
+    let backups = backup::list(&path).unwrap();
+    assert_eq!(backups.len(), 2);
+    assert!(matches!(
+        Vault::unlock(&backups[0].path, "correct horse", None),
+        Err(VaultError::WrongKey)
+    ));
+    let reopened = Vault::unlock(&backups[0].path, "battery staple", None).unwrap();
+    assert_eq!(reopened.list().len(), vault.list().len() - 1);
+    assert_eq!(reopened.header(), &header);
+
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
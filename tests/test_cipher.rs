@@ -0,0 +1,56 @@
+use arustylock::encryption::encryption::{open, rekey, seal};
+use arustylock::encryption::header::{Cipher, VaultHeader};
+
+mod common;
+
+use common::test_vault;
+
+#[test]
+fn test_every_cipher_round_trips() {
+    for cipher in Cipher::ALL.iter() {
+        let (data_key, header) = test_vault(*cipher, "correct horse");
+        let sealed = seal(&data_key, &header, b"[]").unwrap();
+
+        let (parsed, _) = VaultHeader::parse(&sealed).unwrap();
+        assert_eq!(parsed.cipher, *cipher);
+        let (key, _) = parsed.unlock("correct horse", None).unwrap();
+        let (_, plaintext) = open(&key, &sealed).unwrap();
+        assert_eq!(*plaintext, b"[]");
+        assert_eq!(Cipher::from_name(cipher.name()), Some(*cipher));
+    }
+}
+
+#[test]
+fn test_tampering_is_detected_with_every_cipher() {
+    for cipher in Cipher::ALL.iter() {
+        let (data_key, header) = test_vault(*cipher, "correct horse");
+        let sealed = seal(&data_key, &header, b"[]").unwrap();
+        let header_len = header.to_bytes(&data_key).unwrap().len();
+
+        let mut body = sealed.clone();
+        *body.last_mut().unwrap() ^= 1;
+        assert!(open(&data_key, &body).is_err());
+
+        // Flipping the recorded cipher fails the header check
+        let mut switched = sealed.clone();
+        switched[6] = match cipher {
+            Cipher::XChaCha20Poly1305 => 2,
+            Cipher::Aes256Gcm => 1,
+        };
+        assert!(open(&data_key, &switched).is_err());
+
+        assert!(open(&data_key, &sealed[..header_len + 8]).is_err());
+    }
+}
+
+#[test]
+fn test_rekey_can_change_the_cipher() {
+    let (data_key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let sealed = seal(&data_key, &header, b"[]").unwrap();
+
+    let (new_key, new_header) = test_vault(Cipher::Aes256Gcm, "correct horse");
+    let rekeyed = rekey(&sealed, &data_key, &new_key, &new_header).unwrap();
+    let (parsed, plaintext) = open(&new_key, &rekeyed).unwrap();
+    assert_eq!(parsed.cipher, Cipher::Aes256Gcm);
+    assert_eq!(*plaintext, b"[]");
+}
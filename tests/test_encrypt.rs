@@ -1,21 +1,176 @@
 use arustylock::encryption::encryption::*;
-use orion::{aead, aead::SecretKey};
-use std::fs::File;
-const SAMPLE_FILE_PATHS: [&str; 2] = ["./sample1.json", "./sample2.json"];
+use arustylock::encryption::error::VaultError;
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::legacy::*;
+use orion::aead::{self, SecretKey};
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
 
+mod common;
+
+use common::{test_path, test_vault, SAMPLE_FILE_PATHS};
+
+// Copies a sample into the temp dir so encrypting it doesn't touch the fixture
+fn copy_sample(sample: &str, name: &str) -> (PathBuf, File) {
+    let path = test_path(name);
+    fs::copy(sample, &path).unwrap();
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    (path, file)
+}
+
+#[test]
 fn test_encrypt_sanity() {
-    let secret_key = SecretKey::default();
-    for i in 0..SAMPLE_FILE_PATHS.len() {
-        let mut file = File::open(SAMPLE_FILE_PATHS[i]).unwrap();
-        encrypt_data(&mut file, &secret_key);
+    let (secret_key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    for (i, sample) in SAMPLE_FILE_PATHS.iter().enumerate() {
+        let (path, mut file) = copy_sample(sample, &format!("encrypt{}", i));
+        encrypt_data(&mut file, &secret_key, &header).unwrap();
+        assert_ne!(fs::read(&path).unwrap(), fs::read(sample).unwrap());
+        assert_eq!(read_header(&mut file).unwrap(), header);
+        fs::remove_file(path).unwrap();
     }
 }
 
+#[test]
 fn test_decrypt_sanity() {
-    let secret_key = SecretKey::default();
+    let (secret_key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
 
-    for i in 0..SAMPLE_FILE_PATHS.len() {
-        let mut file = File::open(SAMPLE_FILE_PATHS[i]).unwrap();
-        decrypt_data(&mut file, &secret_key)
+    for (i, sample) in SAMPLE_FILE_PATHS.iter().enumerate() {
+        let (path, mut file) = copy_sample(sample, &format!("decrypt{}", i));
+        encrypt_data(&mut file, &secret_key, &header).unwrap();
+        let decrypted = decrypt_data(&mut file, &secret_key).unwrap();
+        assert_eq!(*decrypted, fs::read(sample).unwrap());
+        fs::remove_file(path).unwrap();
     }
 }
+
+#[test]
+fn test_wrong_password_is_rejected() {
+    let (secret_key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let (path, mut file) = copy_sample(SAMPLE_FILE_PATHS[1], "wrong-password");
+    encrypt_data(&mut file, &secret_key, &header).unwrap();
+
+    let header = read_header(&mut file).unwrap();
+    assert!(header.unlock("battery staple", None).is_err());
+    let (key, slot) = header.unlock("correct horse", None).unwrap();
+    assert_eq!(slot, 0);
+    assert!(decrypt_data(&mut file, &key).is_ok());
+    assert!(decrypt_data(&mut file, &SecretKey::default()).is_err());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_decrypt_errors_tell_wrong_key_from_corruption() {
+    // Small chunks so cutting off the end leaves the first chunk intact
+    let (secret_key, mut header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    header.chunk_size = 64;
+    let (path, mut file) = copy_sample(SAMPLE_FILE_PATHS[1], "typed-errors");
+    encrypt_data(&mut file, &secret_key, &header).unwrap();
+
+    assert!(matches!(
+        decrypt_data(&mut file, &SecretKey::default()),
+        Err(VaultError::WrongKey)
+    ));
+
+    let len = file.metadata().unwrap().len();
+    file.set_len(len - 1).unwrap();
+    assert!(matches!(
+        decrypt_data(&mut file, &secret_key),
+        Err(VaultError::Corrupted)
+    ));
+    file.set_len(10).unwrap();
+    assert!(matches!(
+        decrypt_data(&mut file, &secret_key),
+        Err(VaultError::Corrupted)
+    ));
+    assert!(matches!(read_header(&mut file), Err(VaultError::Corrupted)));
+
+    fs::write(&path, fs::read(SAMPLE_FILE_PATHS[1]).unwrap()).unwrap();
+    assert!(matches!(
+        decrypt_data(&mut file, &secret_key),
+        Err(VaultError::Corrupted)
+    ));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_tampered_header_is_rejected() {
+    let (key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let mut sealed = seal(&key, &header, b"[]").unwrap();
+    assert!(open(&key, &sealed).is_ok());
+
+    // Lowering the memory cost of the first slot must not go unnoticed even
+    // though it doesn't change the data key we already hold
+    sealed[34] ^= 1;
+    assert!(open(&key, &sealed).is_err());
+}
+
+#[test]
+fn test_header_round_trip() {
+    let (key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let bytes = header.to_bytes(&key).unwrap();
+    assert!(VaultHeader::has_magic(&bytes));
+    let (parsed, len) = VaultHeader::parse(&bytes).unwrap();
+    assert_eq!(parsed, header);
+    assert_eq!(len, bytes.len());
+    assert!(VaultHeader::parse(&bytes[..len - 1]).is_err());
+    assert!(VaultHeader::parse(b"[{\"domain\": \"\"}]").is_err());
+    assert!(VaultHeader::verify(&bytes, &key).is_ok());
+    assert!(VaultHeader::verify(&bytes, &SecretKey::default()).is_err());
+}
+
+#[test]
+fn test_legacy_store_is_detected_and_opened() {
+    let plaintext = fs::read(SAMPLE_FILE_PATHS[1]).unwrap();
+    let legacy_key = SecretKey::from_slice(LEGACY_KEY).unwrap();
+    let legacy = aead::seal(&legacy_key, &plaintext).unwrap();
+    assert!(is_legacy(&legacy));
+    assert_eq!(*open_legacy(&legacy).unwrap(), plaintext);
+
+    let (key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let migrated = seal(&key, &header, &plaintext).unwrap();
+    assert!(!is_legacy(&migrated));
+    assert!(open_legacy(&migrated).is_err());
+}
+
+#[test]
+fn test_replace_header_keeps_the_body() {
+    let (key, header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let sealed = seal(&key, &header, b"[]").unwrap();
+    let (_, other) = test_vault(Cipher::XChaCha20Poly1305, "battery staple");
+    let new_header = VaultHeader {
+        slots: other.slots,
+        ..header.clone()
+    };
+    let old_len = header.to_bytes(&key).unwrap().len();
+    let new_len = new_header.to_bytes(&key).unwrap().len();
+
+    let replaced = replace_header(&sealed, &key, &new_header).unwrap();
+    assert_eq!(replaced[new_len..], sealed[old_len..]);
+    let (parsed, plaintext) = open(&key, &replaced).unwrap();
+    assert_eq!(parsed, new_header);
+    assert_eq!(*plaintext, b"[]");
+
+    // Only the key slots can change, and only with the data key
+    assert!(replace_header(&sealed, &SecretKey::default(), &new_header).is_err());
+    let (_, another_vault) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    assert!(replace_header(&sealed, &key, &another_vault).is_err());
+}
+
+#[test]
+fn test_rekey() {
+    let (old_key, old_header) = test_vault(Cipher::XChaCha20Poly1305, "correct horse");
+    let sealed = seal(&old_key, &old_header, b"[]").unwrap();
+
+    let (new_key, new_header) = test_vault(Cipher::XChaCha20Poly1305, "battery staple");
+    assert!(rekey(&sealed, &new_key, &new_key, &new_header).is_err());
+
+    let rekeyed = rekey(&sealed, &old_key, &new_key, &new_header).unwrap();
+    assert!(open(&old_key, &rekeyed).is_err());
+    let (header, plaintext) = open(&new_key, &rekeyed).unwrap();
+    assert_eq!(header, new_header);
+    assert_eq!(*plaintext, b"[]");
+}
@@ -0,0 +1,129 @@
+use arustylock::item::*;
+use arustylock::vault::Password;
+use std::fs;
+
+const OTHER_PUBLIC_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGOJCL0xMAvUHjwZOKvcpH31cwIaIgCbRSX1LD1BaXzw other@arustylock";
+
+fn card(number: &str, expiry: &str, security_code: &str) -> Item {
+    Item::Card(Card {
+        holder: String::from("A. Holder"),
+        number: String::from(number),
+        expiry: String::from(expiry),
+        security_code: String::from(security_code),
+    })
+}
+
+fn ssh_key(public_key: &str) -> Item {
+    Item::SshKey(SshKey {
+        private_key: fs::read_to_string("tests/sample_ed25519").unwrap(),
+        public_key: String::from(public_key),
+        passphrase: String::new(),
+    })
+}
+
+#[test]
+fn test_cards_are_checked() {
+    assert!(luhn_valid("4111 1111 1111 1111"));
+    assert!(luhn_valid("5500-0000-0000-0004"));
+    assert!(!luhn_valid("4111 1111 1111 1112"));
+    assert!(!luhn_valid("4111"));
+    assert!(!luhn_valid("4111 1111 1111 111x"));
+
+    assert_eq!(card("4111111111111111", "09/27", "123").validate(), Ok(()));
+    assert_eq!(card("4111111111111111", "", "").validate(), Ok(()));
+    assert_eq!(
+        card("4111111111111112", "09/27", "123").validate(),
+        Err(ItemError::CardNumber)
+    );
+    assert_eq!(
+        card("4111111111111111", "13/27", "123").validate(),
+        Err(ItemError::Expiry)
+    );
+    assert_eq!(
+        card("4111111111111111", "9/27", "123").validate(),
+        Err(ItemError::Expiry)
+    );
+    assert_eq!(
+        card("4111111111111111", "09/27", "12").validate(),
+        Err(ItemError::SecurityCode)
+    );
+    if let Item::Card(card) = card("4111111111111111", "09/2031", "") {
+        assert_eq!(card.expires(), Some((2031, 9)));
+    }
+}
+
+#[test]
+fn test_ssh_keys_are_checked() {
+    let public_key = fs::read_to_string("tests/sample_ed25519.pub").unwrap();
+    assert_eq!(ssh_key(&public_key).validate(), Ok(()));
+    assert_eq!(ssh_key("").validate(), Ok(()));
+    assert_eq!(
+        ssh_key(OTHER_PUBLIC_KEY).validate(),
+        Err(ItemError::KeyMismatch)
+    );
+    assert_eq!(
+        ssh_key("ssh-rsa AAAAC3NzaC1lZDI1NTE5").validate(),
+        Err(ItemError::PublicKey)
+    );
+    let mut cut_short = SshKey::default();
+    cut_short.private_key = fs::read_to_string("tests/sample_ed25519").unwrap();
+    cut_short.private_key.truncate(100);
+    assert_eq!(
+        Item::SshKey(cut_short).validate(),
+        Err(ItemError::PrivateKey)
+    );
+
+    let identity = |email: &str| {
+        let mut identity = Identity::default();
+        identity.email = String::from(email);
+        Item::Identity(identity)
+    };
+    assert_eq!(identity("someone@example.com").validate(), Ok(()));
+    assert_eq!(identity("").validate(), Ok(()));
+    assert_eq!(identity("someone").validate(), Err(ItemError::Email));
+}
+
+#[test]
+fn test_items_keep_their_type() {
+    // Entries from before there were types are logins
+    let old: Vec<Password> = serde_json::from_str(
+        r#"[{"domain": "example.com", "username": "user", "password": "hunter2"}]"#,
+    )
+    .unwrap();
+    assert_eq!(old[0].item, Item::Login(Login::new("user", "hunter2")));
+    // nor from stores where logins had a type but kept their fields loose
+    let loose: Vec<Password> = serde_json::from_str(
+        r#"[{"domain": "example.com", "username": "user", "password": "hunter2",
+             "urls": ["https://example.com"], "item": {"type": "login"}}]"#,
+    )
+    .unwrap();
+    assert_eq!(loose[0].login().unwrap().urls, ["https://example.com"]);
+    assert_eq!(loose[0].login().unwrap().password, "hunter2");
+
+    let entries = vec![
+        Password::new("example.com", "user", "hunter2"),
+        Password::new_item("wifi", Item::Note),
+        Password::new_item("visa", card("4111111111111111", "09/27", "123")),
+        Password::new_item("server", ssh_key("")),
+    ];
+    let json = serde_json::to_string(&entries).unwrap();
+    assert!(json.contains(r#""item":{"type":"card","#));
+    assert!(json.contains(r#""item":{"type":"login","username":"user","#));
+    // Only logins have a username
+    assert_eq!(json.matches("username").count(), 1);
+    let read: Vec<Password> = serde_json::from_str(&json).unwrap();
+    assert_eq!(read, entries);
+    assert_eq!(read[3].item.name(), "SSH key");
+}
+
+#[test]
+fn test_item_kinds() {
+    let names: Vec<&str> = Item::kinds().iter().map(Item::name).collect();
+    assert_eq!(
+        names,
+        ["Login", "Secure note", "Credit card", "Identity", "SSH key"]
+    );
+    let visa = card("4111111111111111", "09/27", "123");
+    assert!(visa.same_kind(&Item::kinds()[2]));
+    assert!(!visa.same_kind(&Item::default()));
+}
@@ -0,0 +1,99 @@
+use arustylock::encryption::encryption::{open, seal};
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::keyslot::{KeySlot, SlotKind};
+use orion::aead::SecretKey;
+
+mod common;
+
+use common::TEST_PARAMS;
+
+const KEYFILE: &[u8] = b"contents of some keyfile";
+
+fn slot(kind: SlotKind, password: &str, keyfile: Option<&[u8]>, key: &SecretKey) -> KeySlot {
+    KeySlot::new(
+        kind,
+        Cipher::XChaCha20Poly1305,
+        &TEST_PARAMS,
+        password,
+        keyfile,
+        key,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_every_slot_opens_the_same_data_key() {
+    let data_key = SecretKey::default();
+    let header = VaultHeader {
+        slots: vec![
+            slot(SlotKind::Password, "correct horse", None, &data_key),
+            slot(SlotKind::Recovery, "battery staple", None, &data_key),
+            slot(SlotKind::Keyfile, "", Some(KEYFILE), &data_key),
+        ],
+        ..VaultHeader::default()
+    };
+    let sealed = seal(&data_key, &header, b"[]").unwrap();
+    let (header, _) = open(&data_key, &sealed).unwrap();
+
+    for (password, keyfile, index) in [
+        ("correct horse", None, 0),
+        ("battery staple", None, 1),
+        ("", Some(KEYFILE), 2),
+    ]
+    .iter()
+    {
+        let (key, slot) = header.unlock(password, *keyfile).unwrap();
+        assert_eq!(slot, *index);
+        assert_eq!(*open(&key, &sealed).unwrap().1, b"[]");
+    }
+    assert!(header.unlock("wrong", None).is_err());
+    assert!(header.unlock("", Some(b"another keyfile")).is_err());
+}
+
+#[test]
+fn test_slots_only_take_their_factors() {
+    let data_key = SecretKey::default();
+    let both = slot(
+        SlotKind::PasswordAndKeyfile,
+        "correct horse",
+        Some(KEYFILE),
+        &data_key,
+    );
+    assert!(both.accepts(true, true));
+    assert!(!both.accepts(true, false));
+    let cipher = Cipher::XChaCha20Poly1305;
+    assert!(both.unlock(cipher, "correct horse", None).is_err());
+    assert!(both
+        .unlock(cipher, "correct horse", Some(b"wrong keyfile"))
+        .is_err());
+    assert_eq!(
+        both.unlock(cipher, "correct horse", Some(KEYFILE))
+            .unwrap()
+            .unprotected_as_bytes(),
+        data_key.unprotected_as_bytes()
+    );
+
+    let keyfile_only = slot(SlotKind::Keyfile, "", Some(KEYFILE), &data_key);
+    assert!(keyfile_only.accepts(false, true));
+    assert!(!keyfile_only.accepts(true, true));
+}
+
+#[test]
+fn test_revoking_a_slot_keeps_the_data_key() {
+    let data_key = SecretKey::default();
+    let mut header = VaultHeader {
+        slots: vec![
+            slot(SlotKind::Password, "correct horse", None, &data_key),
+            slot(SlotKind::Recovery, "battery staple", None, &data_key),
+        ],
+        ..VaultHeader::default()
+    };
+    header.slots.remove(1);
+    let sealed = seal(&data_key, &header, b"[]").unwrap();
+
+    let (parsed, _) = VaultHeader::parse(&sealed).unwrap();
+    assert_eq!(parsed.slots.len(), 1);
+    assert!(parsed.unlock("battery staple", None).is_err());
+    let (key, _) = parsed.unlock("correct horse", None).unwrap();
+    assert!(open(&key, &sealed).is_ok());
+}
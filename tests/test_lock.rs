@@ -0,0 +1,83 @@
+use arustylock::backup;
+use arustylock::lock::StoreLock;
+use arustylock::vault::{StoreError, Vault};
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+mod common;
+
+use common::{entry, test_store};
+
+fn remove_store(path: &Path) {
+    backup::prune(path, 0).unwrap();
+    let _ = fs::remove_file(format!("{}.lock", path.display()));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_save_refuses_to_overwrite_changes() {
+    let path = test_store("lock-changed");
+    let mut first = Vault::unlock(&path, "correct horse", None).unwrap();
+    let mut second = Vault::open(&path, first.key()).unwrap();
+
+    first.insert(entry("example.com"));
+    first.save().unwrap();
+    assert!(!first.changed_on_disk().unwrap());
+    assert!(second.changed_on_disk().unwrap());
+
+    second.insert(entry("example.net"));
+    assert!(matches!(second.save(), Err(StoreError::Changed)));
+    assert!(second.is_dirty());
+    let on_disk = Vault::open(&path, first.key()).unwrap();
+    assert_eq!(on_disk.list(), first.list());
+
+    second.reload().unwrap();
+    assert!(!second.is_dirty());
+    assert!(!second.changed_on_disk().unwrap());
+    assert_eq!(second.list(), first.list());
+    remove_store(&path);
+}
+
+#[test]
+fn test_merge_keeps_both_sides() {
+    let path = test_store("lock-merge");
+    let mut first = Vault::unlock(&path, "correct horse", None).unwrap();
+    let deleted = first.insert(entry("deleted.example"));
+    let updated = first.insert(entry("updated.example"));
+    first.save().unwrap();
+    let mut second = Vault::open(&path, first.key()).unwrap();
+
+    first.delete(&deleted).unwrap();
+    first.insert(entry("example.com"));
+    first.save().unwrap();
+
+    second.update(&updated, entry("example.org")).unwrap();
+    second.insert(entry("example.net"));
+    assert!(matches!(second.save(), Err(StoreError::Changed)));
+    second.merge().unwrap();
+    assert!(second.is_dirty());
+    second.save().unwrap();
+
+    let merged = Vault::open(&path, first.key()).unwrap();
+    let domains: Vec<&str> = merged.list().iter().map(|p| p.domain.as_str()).collect();
+    assert!(!domains.contains(&"deleted.example"));
+    assert!(!domains.contains(&"updated.example"));
+    assert!(domains.contains(&"example.com"));
+    assert!(domains.contains(&"example.org"));
+    assert!(domains.contains(&"example.net"));
+    assert_eq!(merged.list().len(), first.list().len() + 1);
+    remove_store(&path);
+}
+
+#[test]
+fn test_store_lock_is_exclusive() {
+    let path = test_store("lock-exclusive");
+    let lock_path = format!("{}.lock", path.display());
+    let lock = StoreLock::acquire(&path).unwrap();
+    let other = OpenOptions::new().write(true).open(&lock_path).unwrap();
+    assert!(other.try_lock().is_err());
+    drop(lock);
+    assert!(other.try_lock().is_ok());
+    drop(other);
+    remove_store(&path);
+}
@@ -0,0 +1,89 @@
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::keyslot::{KdfParams, KeySlot, SlotKind};
+use arustylock::encryption::recovery::*;
+use orion::aead::SecretKey;
+
+#[test]
+fn test_recovery_key_round_trip() {
+    let recovery_key = RecoveryKey::generate().unwrap();
+    let words = recovery_key.words();
+    assert_eq!(words.len(), PHRASE_WORDS);
+
+    let parsed = RecoveryKey::parse(&words.join(" ")).unwrap();
+    assert_eq!(parsed.secret(), recovery_key.secret());
+    let shouted = words.join(" - ").to_uppercase();
+    assert_eq!(
+        RecoveryKey::parse(&shouted).unwrap().secret(),
+        recovery_key.secret()
+    );
+}
+
+#[test]
+fn test_recovery_key_typos_are_caught() {
+    let words = RecoveryKey::generate().unwrap().words();
+    assert_eq!(
+        RecoveryKey::parse(&words[1..].join(" ")).err(),
+        Some(RecoveryKeyError::WrongLength(PHRASE_WORDS - 1))
+    );
+
+    let mut misspelled = words.clone();
+    misspelled[3] = "xylophone";
+    assert_eq!(
+        RecoveryKey::parse(&misspelled.join(" ")).err(),
+        Some(RecoveryKeyError::UnknownWord(String::from("xylophone")))
+    );
+
+    // Every word is still valid, only the checksum can notice
+    let mut wrong_checksum = words.clone();
+    let last = PHRASE_WORDS - 1;
+    wrong_checksum[last] = if words[last] == "acid" {
+        "acorn"
+    } else {
+        "acid"
+    };
+    assert_eq!(
+        RecoveryKey::parse(&wrong_checksum.join(" ")).err(),
+        Some(RecoveryKeyError::BadChecksum)
+    );
+}
+
+#[test]
+fn test_recovery_key_slot_opens_the_store() {
+    let params = KdfParams {
+        iterations: 3,
+        memory_kib: 8,
+    };
+    let data_key = SecretKey::default();
+    let recovery_key = RecoveryKey::generate().unwrap();
+    let header = VaultHeader {
+        slots: vec![
+            KeySlot::new(
+                SlotKind::Password,
+                Cipher::XChaCha20Poly1305,
+                &params,
+                "correct horse",
+                None,
+                &data_key,
+            )
+            .unwrap(),
+            KeySlot::new(
+                SlotKind::RecoveryKey,
+                Cipher::XChaCha20Poly1305,
+                &params,
+                &recovery_key.secret(),
+                None,
+                &data_key,
+            )
+            .unwrap(),
+        ],
+        ..VaultHeader::default()
+    };
+
+    let (key, slot) = header.unlock_with_recovery_key(&recovery_key).unwrap();
+    assert_eq!(slot, 1);
+    assert_eq!(key.unprotected_as_bytes(), data_key.unprotected_as_bytes());
+    let other = RecoveryKey::generate().unwrap();
+    assert!(header.unlock_with_recovery_key(&other).is_err());
+    // The recovery key slot isn't tried with passwords
+    assert!(header.unlock(&recovery_key.secret(), None).is_err());
+}
@@ -0,0 +1,74 @@
+use arustylock::encryption::recovery::*;
+use arustylock::encryption::shamir::*;
+
+const SECRET: &[u8] = b"sixteen byte key";
+
+#[test]
+fn test_any_threshold_shares_recover_the_secret() {
+    let shares = split(SECRET, 3, 5).unwrap();
+    assert_eq!(shares.len(), 5);
+    for a in 0..5 {
+        for b in a + 1..5 {
+            for c in b + 1..5 {
+                let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                assert_eq!(combine(&subset).unwrap(), SECRET);
+            }
+        }
+    }
+    assert_eq!(combine(&shares).unwrap(), SECRET);
+    assert_ne!(combine(&shares[..2]).unwrap(), SECRET);
+}
+
+#[test]
+fn test_bad_splits_and_shares_are_rejected() {
+    assert_eq!(
+        split(SECRET, 1, 5).err(),
+        Some(ShamirError::InvalidThreshold {
+            threshold: 1,
+            count: 5
+        })
+    );
+    assert!(split(SECRET, 6, 5).is_err());
+
+    let shares = split(SECRET, 2, 3).unwrap();
+    let twice = [shares[0].clone(), shares[0].clone()];
+    assert_eq!(combine(&twice).err(), Some(ShamirError::DuplicateShare));
+    let mut short = shares[1].clone();
+    short.y.pop();
+    assert_eq!(
+        combine(&[shares[0].clone(), short]).err(),
+        Some(ShamirError::MismatchedShares)
+    );
+}
+
+#[test]
+fn test_recovery_key_shares_round_trip() {
+    let recovery_key = RecoveryKey::generate().unwrap();
+    let shares = recovery_key.split(2, 3).unwrap();
+
+    let typed: Vec<RecoveryShare> = shares
+        .iter()
+        .map(|share| {
+            let words = share.words();
+            assert_eq!(words.len(), SHARE_WORDS);
+            match RecoveryPhrase::parse(&words.join(" ")).unwrap() {
+                RecoveryPhrase::Share(share) => share,
+                RecoveryPhrase::Key(_) => panic!("a share was read as a recovery key"),
+            }
+        })
+        .collect();
+    assert_eq!(typed, shares);
+
+    let combined = RecoveryKey::combine(&typed[1..]).unwrap();
+    assert_eq!(combined.secret(), recovery_key.secret());
+    assert_eq!(
+        RecoveryKey::combine(&typed[..1]).err(),
+        Some(ShamirError::NotEnoughShares { needed: 2, got: 1 })
+    );
+
+    let other = RecoveryKey::generate().unwrap().split(3, 3).unwrap();
+    assert_eq!(
+        RecoveryKey::combine(&[typed[0].clone(), other[0].clone()]).err(),
+        Some(ShamirError::MismatchedShares)
+    );
+}
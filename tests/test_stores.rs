@@ -0,0 +1,51 @@
+use arustylock::stores;
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+use common::test_path;
+
+fn test_config_dir(name: &str) -> PathBuf {
+    let dir = test_path(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_vault_names_are_checked() {
+    assert!(stores::valid_name("personal"));
+    assert!(stores::valid_name("on-call_2"));
+    assert!(!stores::valid_name(""));
+    assert!(!stores::valid_name("../data"));
+    assert!(!stores::valid_name("team.lock"));
+    assert!(!stores::valid_name(&"a".repeat(65)));
+}
+
+#[test]
+fn test_vaults_are_found() {
+    let dir = test_config_dir("stores");
+    assert!(stores::list(&dir).unwrap().is_empty());
+    assert_eq!(stores::path(&dir, stores::DEFAULT), dir.join("data"));
+    assert_eq!(stores::path(&dir, "team"), dir.join("vaults").join("team"));
+
+    fs::write(stores::path(&dir, stores::DEFAULT), b"").unwrap();
+    fs::create_dir_all(dir.join("vaults")).unwrap();
+    for name in &["team", "on-call"] {
+        fs::write(stores::path(&dir, name), b"").unwrap();
+    }
+    // What sits next to the vaults isn't one
+    fs::write(dir.join("vaults").join("team.lock"), b"").unwrap();
+    fs::write(
+        dir.join("vaults")
+            .join("team.20261017-120000.000000000.bak"),
+        b"",
+    )
+    .unwrap();
+    fs::create_dir(dir.join("vaults").join("folder")).unwrap();
+    assert_eq!(stores::list(&dir).unwrap(), ["default", "on-call", "team"]);
+
+    fs::remove_file(stores::path(&dir, stores::DEFAULT)).unwrap();
+    assert_eq!(stores::list(&dir).unwrap(), ["on-call", "team"]);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,97 @@
+use arustylock::encryption::encryption::{decrypt_data, open, seal, write_encrypted};
+use arustylock::encryption::header::{Cipher, VaultHeader};
+use arustylock::encryption::stream::{decrypt_stream, encrypt_stream};
+use orion::aead::SecretKey;
+use std::fs::{self, OpenOptions};
+
+mod common;
+
+use common::{test_path, test_vault};
+
+// Small chunks so a few hundred bytes already span several of them
+const CHUNK_SIZE: usize = 64;
+const TAG_LEN: usize = 16;
+
+fn chunked_vault(cipher: Cipher) -> (SecretKey, VaultHeader) {
+    let (key, mut header) = test_vault(cipher, "correct horse");
+    header.chunk_size = CHUNK_SIZE as u32;
+    (key, header)
+}
+
+fn plaintext(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_stream_round_trips_across_chunks() {
+    for cipher in Cipher::ALL.iter() {
+        let (key, header) = chunked_vault(*cipher);
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, 3 * CHUNK_SIZE, 1000].iter() {
+            let data = plaintext(*len);
+            let sealed = encrypt_stream(&key, &header, &mut &data[..], Vec::new()).unwrap();
+            let mut decrypted = Vec::new();
+            let parsed = decrypt_stream(&key, &sealed[..], &mut decrypted).unwrap();
+            assert_eq!(parsed, header);
+            assert_eq!(decrypted, data);
+
+            let (_, opened) = open(&key, &sealed).unwrap();
+            assert_eq!(*opened, data);
+        }
+    }
+}
+
+#[test]
+fn test_truncated_or_reordered_chunks_are_rejected() {
+    let (key, header) = chunked_vault(Cipher::XChaCha20Poly1305);
+    let sealed = seal(&key, &header, &plaintext(3 * CHUNK_SIZE + 10)).unwrap();
+    let body_start = header.to_bytes(&key).unwrap().len() + 19;
+    let sealed_chunk = CHUNK_SIZE + TAG_LEN;
+    assert_eq!(sealed.len(), body_start + 3 * sealed_chunk + 10 + TAG_LEN);
+
+    // Cut at a chunk boundary, in the middle of a chunk and one byte short
+    for len in [
+        body_start + 2 * sealed_chunk,
+        body_start + sealed_chunk + 20,
+        sealed.len() - 1,
+    ]
+    .iter()
+    {
+        assert!(open(&key, &sealed[..*len]).is_err());
+        assert!(decrypt_stream(&key, &sealed[..*len], &mut Vec::new()).is_err());
+    }
+
+    let mut swapped = sealed.clone();
+    let (first, rest) = swapped[body_start..].split_at_mut(sealed_chunk);
+    first.swap_with_slice(&mut rest[..sealed_chunk]);
+    assert!(open(&key, &swapped).is_err());
+
+    let mut extended = sealed.clone();
+    extended.extend_from_slice(&[0u8; TAG_LEN]);
+    assert!(open(&key, &extended).is_err());
+
+    let mut flipped = sealed;
+    flipped[body_start + sealed_chunk + 3] ^= 1;
+    assert!(open(&key, &flipped).is_err());
+}
+
+#[test]
+fn test_vault_files_are_streamed() {
+    let (key, header) = chunked_vault(Cipher::Aes256Gcm);
+    let data = plaintext(10 * CHUNK_SIZE + 5);
+    let path = test_path("stream");
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+
+    write_encrypted(&mut file, &key, &header, &data).unwrap();
+    assert_eq!(*decrypt_data(&mut file, &key).unwrap(), data);
+
+    let len = file.metadata().unwrap().len();
+    file.set_len(len - TAG_LEN as u64).unwrap();
+    assert!(decrypt_data(&mut file, &key).is_err());
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,300 @@
+use arustylock::backup;
+use arustylock::encryption::error::VaultError;
+use arustylock::lock::Fingerprint;
+use arustylock::vault::{folder_path, CustomField, EntryId, Password, StoreError, Vault};
+use orion::aead::SecretKey;
+use std::fs;
+
+mod common;
+
+use common::{entry, test_store};
+
+#[test]
+fn test_vault_changes_are_saved() {
+    let path = test_store("vault-save");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let count = vault.list().len();
+
+    let first = vault.list()[0].id.clone();
+    let added = vault.insert(entry("example.com"));
+    assert_eq!(vault.position(&added), Some(count));
+    let deleted = vault.insert(entry("example.net"));
+    assert_eq!(vault.position(&deleted), Some(count + 1));
+    let old = vault.update(&first, entry("example.org")).unwrap();
+    assert_ne!(old.domain, "example.org");
+    assert_eq!(vault.delete(&deleted).unwrap().domain, "example.net");
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(reopened.list(), vault.list());
+    assert_eq!(reopened.get(&first).unwrap().domain, "example.org");
+    assert_eq!(reopened.get(&added).unwrap().domain, "example.com");
+    assert_eq!(reopened.position(&added), Some(count));
+    assert!(reopened.get(&deleted).is_none());
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_vault_errors() {
+    let path = test_store("vault-errors");
+    assert!(matches!(
+        Vault::unlock(&path, "battery staple", None),
+        Err(VaultError::WrongKey)
+    ));
+    assert!(matches!(
+        Vault::open(&path, &SecretKey::default()),
+        Err(VaultError::WrongKey)
+    ));
+
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let missing = EntryId::generate();
+    assert!(matches!(
+        vault.update(&missing, entry("example.com")),
+        Err(StoreError::NoSuchEntry(_))
+    ));
+    assert!(matches!(
+        vault.delete(&missing),
+        Err(StoreError::NoSuchEntry(_))
+    ));
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        Vault::unlock(&path, "correct horse", None),
+        Err(VaultError::Io(_))
+    ));
+}
+
+#[test]
+fn test_vault_tracks_unsaved_changes() {
+    let path = test_store("vault-dirty");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    // The sample predates IDs, the ones it was given still need saving
+    assert!(vault.is_dirty());
+    vault.save().unwrap();
+    assert!(!vault.is_dirty());
+    assert!(vault.delete(&EntryId::generate()).is_err());
+    assert!(!vault.is_dirty());
+
+    vault.insert(entry("example.com"));
+    assert!(vault.is_dirty());
+    let on_disk = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(on_disk.list().len() + 1, vault.list().len());
+
+    vault.save().unwrap();
+    assert!(!vault.is_dirty());
+    let on_disk = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(on_disk.list(), vault.list());
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_entries_from_older_stores_load() {
+    let path = test_store("vault-old-entries");
+    let current = Vault::unlock(&path, "correct horse", None).unwrap();
+    let old = br#"[{"domain": "example.com", "username": "user", "password": "hunter2"}]"#;
+    let fingerprint = Fingerprint::of(&path).unwrap();
+    let vault = Vault::from_plaintext(
+        &path,
+        current.key(),
+        current.header().clone(),
+        fingerprint,
+        old,
+    )
+    .unwrap();
+    let entry = &vault.list()[0];
+    assert_eq!(entry.domain, "example.com");
+    assert!(!entry.id.to_string().is_empty());
+    assert!(vault.is_dirty());
+    assert!(entry.login().unwrap().urls.is_empty());
+    assert!(entry.tags.is_empty() && entry.notes.is_empty());
+    assert!(entry.fields.is_empty());
+    assert_eq!(entry.created, None);
+    assert_eq!(entry.modified, None);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_entries_keep_their_times() {
+    let path = test_store("vault-times");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let mut new = entry("example.com");
+    new.login_mut().unwrap().urls = vec![String::from("https://example.com/login")];
+    new.notes = String::from("security question: blue");
+    new.tags = vec![String::from("work"), String::from("email")];
+    let id = vault.insert(new);
+    let added = vault.get(&id).unwrap().clone();
+    assert!(added.created.is_some());
+    assert_eq!(added.modified, added.created);
+
+    vault.update(&id, entry("example.org")).unwrap();
+    let updated = vault.get(&id).unwrap().clone();
+    assert_eq!(updated.created, added.created);
+    assert!(updated.modified >= added.modified);
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(reopened.get(&id), Some(&updated));
+    vault.update(&id, added.clone()).unwrap();
+    vault.save().unwrap();
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(reopened.get(&id).unwrap().tags, added.tags);
+    assert_eq!(reopened.get(&id).unwrap().login(), added.login());
+    assert_eq!(reopened.get(&id).unwrap().notes, added.notes);
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_entry_ids_stay_the_same() {
+    let path = test_store("vault-ids");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let old: Vec<EntryId> = vault.list().iter().map(|entry| entry.id.clone()).collect();
+    let added = vault.insert(entry("example.com"));
+    assert!(!old.contains(&added));
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert!(!reopened.is_dirty());
+    let ids: Vec<EntryId> = reopened
+        .list()
+        .iter()
+        .map(|entry| entry.id.clone())
+        .collect();
+    assert_eq!(ids[..old.len()], old[..]);
+    assert_eq!(ids.last(), Some(&added));
+
+    // An entry can't take an ID another one has
+    let mut copy = entry("example.net");
+    copy.id = added.clone();
+    let copied = vault.insert(copy);
+    assert_ne!(copied, added);
+    assert_eq!(vault.get(&added).unwrap().domain, "example.com");
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_entries_keep_previous_logins() {
+    let path = test_store("vault-history");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    vault.set_history_depth(2);
+    let id = vault.insert(entry("example.com"));
+
+    // Only a changed login is kept
+    let mut changed = vault.get(&id).unwrap().clone();
+    changed.notes = String::from("nothing to keep");
+    vault.update(&id, changed).unwrap();
+    assert!(vault.get(&id).unwrap().history.is_empty());
+
+    for password in &["first", "second", "third"] {
+        let mut changed = vault.get(&id).unwrap().clone();
+        changed.login_mut().unwrap().password = password.to_string();
+        vault.update(&id, changed).unwrap();
+    }
+    let history: Vec<&str> = vault
+        .get(&id)
+        .unwrap()
+        .history
+        .iter()
+        .map(|login| login.password.as_str())
+        .collect();
+    assert_eq!(history, ["second", "first"]);
+
+    // An update that doesn't carry the history keeps it all the same
+    vault
+        .update(&id, Password::new("example.com", "admin", "third"))
+        .unwrap();
+    let updated = vault.get(&id).unwrap().clone();
+    assert_eq!(updated.history[0].username, "user");
+    assert_eq!(updated.history[0].password, "third");
+    assert_eq!(updated.history[1].password, "second");
+
+    vault.restore_login(&id, 1).unwrap();
+    let restored = vault.get(&id).unwrap().clone();
+    let login = restored.login().unwrap();
+    assert_eq!(
+        (login.username.as_str(), login.password.as_str()),
+        ("user", "second")
+    );
+    assert_eq!(restored.history[0].username, "admin");
+    assert_eq!(restored.history[1].password, "third");
+    assert!(matches!(
+        vault.restore_login(&id, 2),
+        Err(StoreError::NoSuchLogin(_, 2))
+    ));
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(reopened.get(&id), Some(&restored));
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_custom_fields_are_saved() {
+    let path = test_store("vault-fields");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    let mut new = entry("bank.example");
+    new.fields = vec![
+        CustomField::new("account", "12345678", false),
+        CustomField::new("pin", "0000", true),
+    ];
+    let id = vault.insert(new);
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    let fields = &reopened.get(&id).unwrap().fields;
+    assert_eq!(fields, &vault.get(&id).unwrap().fields);
+    assert!(!fields[0].concealed);
+    assert!(fields[1].concealed);
+
+    // Fields written without the flag are plain
+    let old = br#"[{"domain": "d", "username": "u", "password": "p", "fields": [{"name": "n", "value": "v"}]}]"#;
+    let fingerprint = Fingerprint::of(&path).unwrap();
+    let vault = Vault::from_plaintext(&path, vault.key(), vault.header().clone(), fingerprint, old)
+        .unwrap();
+    assert_eq!(vault.list()[0].fields, [CustomField::new("n", "v", false)]);
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_entries_move_between_folders() {
+    assert_eq!(folder_path("/work//aws/ prod /"), "work/aws/prod");
+    assert_eq!(folder_path(" / "), "");
+
+    let path = test_store("vault-folders");
+    let mut vault = Vault::unlock(&path, "correct horse", None).unwrap();
+    assert!(vault.folders().is_empty());
+    let mut console = entry("console");
+    console.folder = String::from("work/aws/prod/");
+    let console = vault.insert(console);
+    let mail = vault.insert(entry("mail"));
+    assert_eq!(vault.get(&console).unwrap().path(), "work/aws/prod/console");
+    assert_eq!(vault.get(&mail).unwrap().path(), "mail");
+    assert_eq!(vault.folders(), ["work", "work/aws", "work/aws/prod"]);
+
+    vault.move_to(&mail, "/personal/").unwrap();
+    vault.move_to(&console, "work/aws").unwrap();
+    assert_eq!(vault.folders(), ["personal", "work", "work/aws"]);
+    assert!(matches!(
+        vault.move_to(&EntryId::generate(), "work"),
+        Err(StoreError::NoSuchEntry(_))
+    ));
+    vault.save().unwrap();
+
+    let reopened = Vault::open(&path, vault.key()).unwrap();
+    assert_eq!(reopened.get(&mail).unwrap().folder, "personal");
+    assert_eq!(reopened.get(&console).unwrap().path(), "work/aws/console");
+    backup::prune(&path, 0).unwrap();
+    fs::remove_file(format!("{}.lock", path.display())).unwrap();
+    fs::remove_file(path).unwrap();
+}